@@ -1,12 +1,18 @@
 BEGIN TRANSACTION;
 
+-- An album is identified by its artist, title and folder, albums tagged with the same MusicBrainz release
+-- are merged even when their files are spread over several folders. Older databases are migrated by db::init_database
 CREATE TABLE IF NOT EXISTS album (
-    location_on_disk TEXT PRIMARY KEY NOT NULL,
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    location_on_disk TEXT NOT NULL,
     cover_path TEXT,
     title TEXT NOT NULL,
     artist TEXT NOT NULL,
     year INTEGER,
-    genre TEXT
+    genre TEXT,
+    release_mbid TEXT,
+    compilation INTEGER NOT NULL DEFAULT 0,
+    UNIQUE (artist, title, location_on_disk)
 );
 
 CREATE TABLE IF NOT EXISTS song (
@@ -18,19 +24,193 @@ CREATE TABLE IF NOT EXISTS song (
     album_artist TEXT NOT NULL,
     track_number INTEGER DEFAULT 0,
     disc_number INTEGER DEFAULT 0,
+    disc_total INTEGER DEFAULT 0,
+    disc_subtitle TEXT,
     duration INTEGER DEFAULT 0,
     year INTEGER,
-    genre TEXT
+    genre TEXT,
+    album_id INTEGER,
+    -- Read from the audio stream. bitrate is in kbps, bit_depth is only known for lossless codecs
+    codec TEXT,
+    container TEXT,
+    sample_rate INTEGER,
+    bit_depth INTEGER,
+    channels INTEGER,
+    bitrate INTEGER,
+    lossless INTEGER NOT NULL DEFAULT 0,
+    composer TEXT,
+    conductor TEXT,
+    lyricist TEXT,
+    bpm INTEGER,
+    initial_key TEXT,
+    mood TEXT,
+    comment TEXT,
+    label TEXT,
+    catalog_number TEXT,
+    isrc TEXT,
+    barcode TEXT,
+    original_year INTEGER,
+    release_date TEXT,
+    title_sort TEXT,
+    artist_sort TEXT,
+    album_sort TEXT,
+    album_artist_sort TEXT,
+    recording_mbid TEXT,
+    track_mbid TEXT,
+    release_mbid TEXT,
+    release_group_mbid TEXT,
+    -- Comma separated when there are several artists
+    artist_mbids TEXT,
+    album_artist_mbids TEXT
 );
 
-CREATE TRIGGER IF NOT EXISTS update_cover_path AFTER UPDATE OF cover_path ON album
+CREATE TABLE IF NOT EXISTS playlist (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+);
+
+-- Entries reference songs by path rather than by foreign key so that
+-- rescanning a directory (which replaces song rows) keeps playlists intact
+CREATE TABLE IF NOT EXISTS playlist_entry (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    playlist_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    file_path TEXT NOT NULL
+);
+
+-- Kept apart from song so that rescanning a directory doesn't reset ratings set in Sable.
+-- rating is in half stars (0-10)
+CREATE TABLE IF NOT EXISTS song_rating (
+    file_path TEXT PRIMARY KEY NOT NULL,
+    rating INTEGER NOT NULL DEFAULT 0,
+    loved INTEGER NOT NULL DEFAULT 0
+);
+
+-- One row per time a song was loaded by the player. listened_ms excludes time spent paused
+CREATE TABLE IF NOT EXISTS play_event (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    file_path TEXT NOT NULL,
+    started_at INTEGER NOT NULL,
+    listened_ms INTEGER NOT NULL DEFAULT 0,
+    completed INTEGER NOT NULL DEFAULT 0,
+    skipped INTEGER NOT NULL DEFAULT 0
+);
+
+-- Plays waiting to be submitted to a scrobbling service. Metadata is copied so edits or rescans don't change what is sent
+CREATE TABLE IF NOT EXISTS scrobble_queue (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    service TEXT NOT NULL,
+    file_path TEXT NOT NULL,
+    artist TEXT NOT NULL,
+    title TEXT NOT NULL,
+    album_title TEXT,
+    album_artist TEXT,
+    track_number INTEGER,
+    duration INTEGER,
+    recording_mbid TEXT,
+    release_mbid TEXT,
+    artist_mbids TEXT,
+    timestamp INTEGER NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    -- Set when the service refused the scrobble itself, it is kept but not sent again
+    rejected INTEGER NOT NULL DEFAULT 0
+);
+
+-- One row per tag edit, a batch edit of several songs included. undo_of is the edit an undo reverted,
+-- undos are edits themselves so they can be undone in turn
+CREATE TABLE IF NOT EXISTS edit_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    edited_at INTEGER NOT NULL,
+    undo_of INTEGER,
+    undone_at INTEGER
+);
+
+-- The fields an edit changed in each file, as JSON lists of tag fields with their values before and after it.
+-- Pictures aren't kept, pictures_changed tells that undoing the edit can't restore them. cover_path is the cover
+-- the edit set in the library, previous_cover_path the one it replaced
+CREATE TABLE IF NOT EXISTS edit_history_file (
+    edit_id INTEGER NOT NULL,
+    file_path TEXT NOT NULL,
+    previous_fields TEXT NOT NULL,
+    new_fields TEXT NOT NULL,
+    pictures_changed INTEGER NOT NULL DEFAULT 0,
+    previous_cover_path TEXT,
+    cover_path TEXT,
+    PRIMARY KEY (edit_id, file_path)
+);
+
+-- User preferences that the backend needs, e.g. which scrobbling services are enabled. Values are plain text or JSON
+CREATE TABLE IF NOT EXISTS setting (
+    key TEXT PRIMARY KEY NOT NULL,
+    value TEXT NOT NULL
+);
+
+-- Every artist credited on a song, split from its artist fields. role is main, featured, remixer or album_artist
+CREATE TABLE IF NOT EXISTS song_artist (
+    file_path TEXT NOT NULL,
+    artist TEXT NOT NULL,
+    role TEXT NOT NULL,
+    position INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (file_path, artist, role)
+);
+
+-- image_path is found by the scanner, custom_image_path is chosen in Sable and takes precedence.
+-- Artists without an image don't need a row
+CREATE TABLE IF NOT EXISTS artist (
+    name TEXT PRIMARY KEY NOT NULL,
+    image_path TEXT,
+    custom_image_path TEXT
+);
+
+DROP TRIGGER IF EXISTS update_cover_path;
+CREATE TRIGGER update_cover_path AFTER UPDATE OF cover_path ON album
 FOR EACH ROW
 BEGIN
     UPDATE song
     SET cover_path = NEW.cover_path
-    WHERE album_title = NEW.title AND album_artist = NEW.artist;
+    WHERE album_id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS delete_song_artist AFTER DELETE ON song
+FOR EACH ROW
+BEGIN
+    DELETE FROM song_artist WHERE file_path = OLD.file_path;
 END;
 
 CREATE INDEX IF NOT EXISTS idx_album_artist ON album (artist);
 CREATE INDEX IF NOT EXISTS idx_song_album ON song (album_title, album_artist);
+CREATE INDEX IF NOT EXISTS idx_song_album_id ON song (album_id);
+CREATE INDEX IF NOT EXISTS idx_song_artist ON song_artist (artist, role);
+CREATE INDEX IF NOT EXISTS idx_playlist_entry ON playlist_entry (playlist_id, position);
+CREATE INDEX IF NOT EXISTS idx_play_event_song ON play_event (file_path);
+CREATE INDEX IF NOT EXISTS idx_play_event_started ON play_event (started_at);
+CREATE INDEX IF NOT EXISTS idx_scrobble_queue ON scrobble_queue (service, next_attempt_at);
+
+-- Views are recreated on every start so changes to them apply to existing databases
+DROP VIEW IF EXISTS song_with_stats;
+DROP VIEW IF EXISTS song_play_stats;
+
+CREATE VIEW song_play_stats AS
+SELECT
+    file_path,
+    SUM(completed) AS play_count,
+    SUM(skipped) AS skip_count,
+    MAX(CASE WHEN skipped = 0 THEN started_at END) AS last_played
+FROM play_event GROUP BY file_path;
+
+CREATE VIEW song_with_stats AS
+SELECT
+    song.*,
+    COALESCE(song_play_stats.play_count, 0) AS play_count,
+    COALESCE(song_play_stats.skip_count, 0) AS skip_count,
+    song_play_stats.last_played,
+    COALESCE(song_rating.rating, 0) / 2.0 AS rating,
+    COALESCE(song_rating.loved, 0) AS loved
+FROM song
+LEFT JOIN song_play_stats ON song_play_stats.file_path = song.file_path
+LEFT JOIN song_rating ON song_rating.file_path = song.file_path;
 COMMIT;
\ No newline at end of file
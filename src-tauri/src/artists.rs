@@ -0,0 +1,284 @@
+use rusqlite::{params, Connection};
+
+use crate::settings;
+use crate::tags::ArtistTags;
+
+pub(crate) const ARTIST_SEPARATORS_KEY: &str = "artist_separators";
+pub(crate) const FEATURING_SEPARATORS_KEY: &str = "featuring_separators";
+pub(crate) const SPLIT_EXCEPTIONS_KEY: &str = "artist_split_exceptions";
+
+// Separate artists credited equally, e.g. "A; B". "&" is part of too many band names to split on by default
+pub(crate) const DEFAULT_ARTIST_SEPARATORS: [&str; 2] = [";", "/"];
+pub(crate) const DEFAULT_FEATURING_SEPARATORS: [&str; 3] = ["feat.", "ft.", "featuring"];
+pub(crate) const DEFAULT_SPLIT_EXCEPTIONS: [&str; 12] = [
+    "AC/DC",
+    "Above & Beyond",
+    "Belle & Sebastian",
+    "Chase & Status",
+    "Crosby, Stills, Nash & Young",
+    "Earth, Wind & Fire",
+    "Emerson, Lake & Palmer",
+    "Hall & Oates",
+    "Mumford & Sons",
+    "Simon & Garfunkel",
+    "Tyler, The Creator",
+    "Years & Years",
+];
+const FEATURED_LIST_SEPARATORS: [&str; 2] = [",", "&"];
+
+const REMIX_SUFFIXES: [&str; 2] = [" remix", " rmx"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Role {
+    Main,
+    Featured,
+    Remixer,
+    AlbumArtist,
+}
+
+impl Role {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Main => "main",
+            Role::Featured => "featured",
+            Role::Remixer => "remixer",
+            Role::AlbumArtist => "album_artist",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Credit {
+    pub name: String,
+    pub role: Role,
+}
+
+#[derive(Debug)]
+pub(crate) struct ArtistSplitter {
+    separators: Vec<String>,
+    featuring: Vec<String>,
+    exceptions: Vec<String>,
+}
+
+fn trim_name(name: &str) -> &str {
+    name.trim_matches(|c: char| c.is_whitespace() || "()[],".contains(c))
+}
+
+impl ArtistSplitter {
+    pub fn load(conn: &Connection) -> Self {
+        ArtistSplitter {
+            separators: settings::get_list_setting(conn, ARTIST_SEPARATORS_KEY, &DEFAULT_ARTIST_SEPARATORS),
+            featuring: settings::get_list_setting(conn, FEATURING_SEPARATORS_KEY, &DEFAULT_FEATURING_SEPARATORS),
+            exceptions: settings::get_list_setting(conn, SPLIT_EXCEPTIONS_KEY, &DEFAULT_SPLIT_EXCEPTIONS),
+        }
+    }
+
+    fn find_separator(&self, text: &str, separators: &[String]) -> Option<(usize, usize)> {
+        let lowered = text.to_ascii_lowercase();
+        let protected = self
+            .exceptions
+            .iter()
+            .map(|exception| exception.to_ascii_lowercase())
+            .filter(|exception| !exception.is_empty())
+            .flat_map(|exception| {
+                lowered
+                    .match_indices(&exception)
+                    .map(|(start, _)| (start, start + exception.len()))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let is_word = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+
+        (0..lowered.len())
+            .filter(|i| lowered.is_char_boundary(*i))
+            .filter(|i| !protected.iter().any(|(start, end)| (*start..*end).contains(i)))
+            .find_map(|i| {
+                separators
+                    .iter()
+                    .map(|separator| separator.to_ascii_lowercase())
+                    .filter(|separator| !separator.is_empty() && lowered[i..].starts_with(separator.as_str()))
+                    .filter(|separator| {
+                        let end = i + separator.len();
+                        (!is_word(separator.chars().next()) || !is_word(lowered[..i].chars().next_back()))
+                            && (!is_word(separator.chars().next_back()) || !is_word(lowered[end..].chars().next()))
+                    })
+                    .map(|separator| (i, i + separator.len()))
+                    .max_by_key(|(start, end)| end - start)
+            })
+    }
+
+    fn split(&self, text: &str, separators: &[String]) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut rest = text;
+
+        while let Some((start, end)) = self.find_separator(rest, separators) {
+            names.push(trim_name(&rest[..start]).to_string());
+            rest = &rest[end..];
+        }
+        names.push(trim_name(rest).to_string());
+
+        names.into_iter().filter(|name| !name.is_empty()).collect()
+    }
+
+    fn split_featured(&self, text: &str) -> Vec<String> {
+        let mut separators = self.separators.clone();
+        separators.extend(FEATURED_LIST_SEPARATORS.iter().map(|separator| separator.to_string()));
+        self.split(text, &separators)
+    }
+
+    fn split_featuring(&self, text: &str) -> (Vec<String>, Vec<String>) {
+        match self.find_separator(text, &self.featuring) {
+            Some((start, end)) => (self.split(&text[..start], &self.separators), self.split_featured(&text[end..])),
+            None => (self.split(text, &self.separators), Vec::new()),
+        }
+    }
+
+    pub fn main_artists(&self, text: &str) -> Vec<String> {
+        self.split_featuring(text).0
+    }
+
+    fn title_credits(&self, title: &str) -> Vec<Credit> {
+        let mut credits = Vec::new();
+        let mut rest = title;
+
+        while let Some(open) = rest.find(['(', '[']) {
+            let close = if rest[open..].starts_with('(') { ')' } else { ']' };
+            let Some(length) = rest[open..].find(close) else { break };
+            let group = rest[open + 1..open + length].trim();
+            rest = &rest[open + length + 1..];
+
+            if let Some((_, end)) = self.find_separator(group, &self.featuring).filter(|(start, _)| *start == 0) {
+                let names = self.split_featured(&group[end..]);
+                credits.extend(names.into_iter().map(|name| Credit { name, role: Role::Featured }));
+                continue;
+            }
+
+            let lowered = group.to_ascii_lowercase();
+            if let Some(suffix) = REMIX_SUFFIXES.iter().find(|suffix| lowered.ends_with(*suffix)) {
+                let names = self.split(&group[..group.len() - suffix.len()], &self.separators);
+                credits.extend(names.into_iter().map(|name| Credit { name, role: Role::Remixer }));
+            }
+        }
+
+        credits
+    }
+
+    pub fn credits(&self, tags: &ArtistTags, artist: &str, album_artist: &str, title: &str) -> Vec<Credit> {
+        let values = |tagged: &[String], fallback: &str| {
+            if tagged.is_empty() { vec![fallback.to_string()] } else { tagged.to_vec() }
+        };
+
+        let mut credits = Vec::new();
+        for value in values(&tags.artists, artist) {
+            let (main, featured) = self.split_featuring(&value);
+            credits.extend(main.into_iter().map(|name| Credit { name, role: Role::Main }));
+            credits.extend(featured.into_iter().map(|name| Credit { name, role: Role::Featured }));
+        }
+
+        credits.extend(self.title_credits(title));
+        credits.extend(tags.remixers.iter().flat_map(|value| self.split(value, &self.separators)).map(|name| Credit { name, role: Role::Remixer }));
+
+        for value in values(&tags.album_artists, album_artist) {
+            let (main, featured) = self.split_featuring(&value);
+            credits.extend(main.into_iter().chain(featured).map(|name| Credit { name, role: Role::AlbumArtist }));
+        }
+
+        // An artist credited as a main artist isn't also featured on the same song
+        let mut unique: Vec<Credit> = Vec::new();
+        for credit in credits {
+            let same_name = |other: &Credit| other.name.to_lowercase() == credit.name.to_lowercase();
+            let duplicate = unique.iter().any(|other| {
+                same_name(other) && (other.role == credit.role || (other.role == Role::Main && credit.role == Role::Featured))
+            });
+
+            if !duplicate {
+                unique.push(credit);
+            }
+        }
+
+        unique
+    }
+}
+
+pub(crate) fn save_credits(conn: &Connection, file_path: &str, credits: &[Credit]) -> Result<(), rusqlite::Error> {
+    conn.execute("DELETE FROM song_artist WHERE file_path = ?1", params![file_path])?;
+
+    let mut insert = conn.prepare(
+        "INSERT OR IGNORE INTO song_artist (file_path, artist, role, position) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    for (position, credit) in credits.iter().enumerate() {
+        insert.execute(params![file_path, credit.name, credit.role.name(), position])?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn index_missing_credits(conn: &mut Connection) -> Result<usize, rusqlite::Error> {
+    let splitter = ArtistSplitter::load(conn);
+    let tx = conn.transaction()?;
+
+    let songs = tx
+        .prepare(
+            "SELECT file_path, artist, album_artist, title FROM song
+            WHERE NOT EXISTS (SELECT 1 FROM song_artist WHERE song_artist.file_path = song.file_path)",
+        )?
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for (file_path, artist, album_artist, title) in &songs {
+        let credits = splitter.credits(&ArtistTags::default(), artist, album_artist, title);
+        save_credits(&tx, file_path, &credits)?;
+    }
+
+    tx.commit()?;
+    Ok(songs.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn splitter() -> ArtistSplitter {
+        let list = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+        ArtistSplitter {
+            separators: list(&DEFAULT_ARTIST_SEPARATORS),
+            featuring: list(&DEFAULT_FEATURING_SEPARATORS),
+            exceptions: list(&DEFAULT_SPLIT_EXCEPTIONS),
+        }
+    }
+
+    #[test]
+    fn band_names_with_ampersands_are_not_split() {
+        let splitter = splitter();
+
+        assert_eq!(splitter.split_featuring("Simon & Garfunkel").0, ["Simon & Garfunkel"]);
+        assert_eq!(splitter.split_featuring("Earth, Wind & Fire").0, ["Earth, Wind & Fire"]);
+        assert_eq!(splitter.split_featuring("AC/DC").0, ["AC/DC"]);
+        assert_eq!(splitter.split_featuring("A; B/C").0, ["A", "B", "C"]);
+    }
+
+    #[test]
+    fn featured_lists_split_on_commas_and_ampersands() {
+        let (main, featured) = splitter().split_featuring("A feat. B, C & D");
+
+        assert_eq!(main, ["A"]);
+        assert_eq!(featured, ["B", "C", "D"]);
+    }
+
+    #[test]
+    fn featured_lists_keep_exceptions_whole() {
+        let (main, featured) = splitter().split_featuring("A ft. Earth, Wind & Fire & Tyler, The Creator");
+
+        assert_eq!(main, ["A"]);
+        assert_eq!(featured, ["Earth, Wind & Fire", "Tyler, The Creator"]);
+    }
+
+    #[test]
+    fn title_credits_split_featured_artists() {
+        let credits = splitter().title_credits("Song (feat. B & C) [D Remix]");
+        let names = credits.iter().map(|credit| (credit.name.as_str(), credit.role)).collect::<Vec<_>>();
+
+        assert_eq!(names, [("B", Role::Featured), ("C", Role::Featured), ("D", Role::Remixer)]);
+    }
+}
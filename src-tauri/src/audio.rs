@@ -1,54 +1,406 @@
+use rodio::decoder::DecoderError;
+use rodio::source::EmptyCallback;
 use rodio::Source;
 use std::error::Error;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use symphonia::core::codecs::{
+    CodecType, DecoderOptions, CODEC_TYPE_AAC, CODEC_TYPE_ALAC, CODEC_TYPE_FLAC, CODEC_TYPE_MONKEYS_AUDIO,
+    CODEC_TYPE_MP1, CODEC_TYPE_MP2, CODEC_TYPE_MP3, CODEC_TYPE_MUSEPACK, CODEC_TYPE_NULL, CODEC_TYPE_OPUS,
+    CODEC_TYPE_PCM_ALAW, CODEC_TYPE_PCM_MULAW, CODEC_TYPE_SPEEX, CODEC_TYPE_TTA, CODEC_TYPE_VORBIS,
+    CODEC_TYPE_WAVPACK, CODEC_TYPE_WMA,
+};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::TimeBase;
+use tauri::Manager;
 
+use crate::db;
+use crate::scrobble;
+use crate::tags;
 use crate::MusicPlayer;
 
+const COMPLETION_MARGIN: Duration = Duration::from_secs(3);
+
+pub struct NowPlaying {
+    file_path: String,
+    started_at: u64,
+    duration: Option<Duration>,
+    listened: Duration,
+    resumed_at: Option<Instant>,
+}
+
+impl NowPlaying {
+    fn new(file_path: String, duration: Option<Duration>) -> Self {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        NowPlaying {
+            file_path,
+            started_at,
+            duration,
+            listened: Duration::ZERO,
+            resumed_at: Some(Instant::now()),
+        }
+    }
+
+    fn pause(&mut self) {
+        if let Some(resumed_at) = self.resumed_at.take() {
+            self.listened += resumed_at.elapsed();
+        }
+    }
+
+    fn resume(&mut self) {
+        self.resumed_at.get_or_insert_with(Instant::now);
+    }
+
+    // Never more than the duration, the sink can sit empty for a while before the frontend stops playback
+    fn listened(&self) -> Duration {
+        let listened = self.listened + self.resumed_at.map(|t| t.elapsed()).unwrap_or_default();
+        self.duration.map_or(listened, |duration| listened.min(duration))
+    }
+}
+
+fn finish_now_playing(state: &MusicPlayer, app: tauri::AppHandle) {
+    let Some(now_playing) = state.now_playing.lock().unwrap().take() else { return };
+    let reached_end = state.sink.empty()
+        || now_playing.duration.is_some_and(|d| now_playing.listened() + COMPLETION_MARGIN >= d);
+
+    record_play(now_playing, reached_end, app);
+}
+
+fn record_play(now_playing: NowPlaying, reached_end: bool, app: tauri::AppHandle) {
+    let listened = now_playing.listened();
+    let event = db::PlayEvent {
+        file_path: now_playing.file_path,
+        started_at: now_playing.started_at,
+        listened_ms: listened.as_millis() as u64,
+        completed: reached_end,
+        skipped: !reached_end,
+    };
+
+    if let Err(e) = db::record_play_event(&event, app.clone()) {
+        println!("Failed to record play event for {}: {}", event.file_path, e);
+    }
+
+    match scrobble::queue_play(&app, &event.file_path, event.started_at, listened, now_playing.duration) {
+        Ok(true) => scrobble::spawn_flush(&app),
+        Ok(false) => {}
+        Err(e) => println!("Failed to queue scrobble for {}: {}", event.file_path, e),
+    }
+}
+
+fn append_song(state: &MusicPlayer, file_path: String, source: rodio::Decoder<BufReader<File>>, app: tauri::AppHandle) {
+    let duration = source.total_duration();
+    state.sink.append(EmptyCallback::<f32>::new(Box::new(move || start_song(&file_path, duration, &app))));
+    state.sink.append(source);
+}
+
+fn start_song(file_path: &str, duration: Option<Duration>, app: &tauri::AppHandle) {
+    let state = app.state::<MusicPlayer>();
+    let finished = state.now_playing.lock().unwrap().replace(NowPlaying::new(file_path.to_string(), duration));
+
+    // Recording plays and scrobbling hit the database and network, keep them off the audio thread
+    let file_path = file_path.to_string();
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        if let Some(finished) = finished {
+            record_play(finished, true, app.clone());
+        }
+        scrobble::send_playing_now(&app, &file_path);
+    });
+}
+
+pub const AUDIO_EXTENSIONS: [&str; 20] = [
+    "mp3", "mp2", "flac", "m4a", "m4b", "mp4", "aac", "ogg", "oga", "opus", "spx", "wav", "aif", "aiff", "aifc",
+    "ape", "wv", "mpc", "dsf", "dff",
+];
+
+const LOSSLESS_CODECS: [&str; 7] = ["FLAC", "ALAC", "WavPack", "APE", "TTA", "PCM", "DSD"];
+
+pub fn has_audio_signature(path: &Path) -> bool {
+    let mut header = [0; 12];
+    let Ok(mut file) = File::open(path) else { return false };
+    if file.read_exact(&mut header).is_err() {
+        return false;
+    }
+
+    match &header {
+        [b'I', b'D', b'3', ..] | [b'f', b'L', b'a', b'C', ..] | [b'O', b'g', b'g', b'S', ..] => true,
+        [b'M', b'A', b'C', b' ', ..] | [b'w', b'v', b'p', b'k', ..] | [b'M', b'P', b'C', b'K', ..] => true,
+        [b'D', b'S', b'D', b' ', ..] | [b'F', b'R', b'M', b'8', ..] => true,
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E'] => true,
+        [b'F', b'O', b'R', b'M', _, _, _, _, b'A', b'I', b'F', b'F' | b'C'] => true,
+        [_, _, _, _, b'f', b't', b'y', b'p', b'M', b'4', b'A' | b'B', _] => true,
+        // MPEG audio and ADTS frames start with 11 set sync bits
+        [0xFF, second, ..] => second & 0xE0 == 0xE0,
+        _ => false,
+    }
+}
+
 pub fn get_source(file_path: &str) -> Result<rodio::Decoder<BufReader<File>>, Box<dyn Error>> {
     let file = File::open(file_path)?;
     let buffer = BufReader::new(file);
-    Ok(rodio::Decoder::new(buffer)?)
+
+    match rodio::Decoder::new(buffer) {
+        Ok(decoder) => Ok(decoder),
+        Err(DecoderError::UnrecognizedFormat) => {
+            let codec = read_properties(file_path)
+                .ok()
+                .and_then(|properties| properties.codec)
+                .unwrap_or_else(|| "this codec".into());
+            Err(format!("Unsupported codec: {} can't be played yet", codec).into())
+        }
+        Err(e) => Err(e.into()),
+    }
 }
 
-pub fn get_duration(file_path: &str) -> Result<u64, Box<dyn Error>> {
-    let source = get_source(file_path)?;
-    let duration = source.total_duration();
+#[derive(Debug, Default, Clone)]
+pub struct AudioProperties {
+    // In milliseconds
+    pub duration: u64,
+    pub codec: Option<String>,
+    pub container: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub bit_depth: Option<u32>,
+    pub channels: Option<u32>,
+    // In kbps
+    pub bitrate: Option<u32>,
+    pub lossless: bool,
+}
 
-    return match duration {
-        Some(d) => Ok(d.as_secs()),
-        None => Err("Could not read song duration".into()),
+fn codec_name(codec: CodecType) -> Option<&'static str> {
+    let name = match codec {
+        CODEC_TYPE_FLAC => "FLAC",
+        CODEC_TYPE_ALAC => "ALAC",
+        CODEC_TYPE_WAVPACK => "WavPack",
+        CODEC_TYPE_MONKEYS_AUDIO => "APE",
+        CODEC_TYPE_TTA => "TTA",
+        CODEC_TYPE_MP1 => "MP1",
+        CODEC_TYPE_MP2 => "MP2",
+        CODEC_TYPE_MP3 => "MP3",
+        CODEC_TYPE_AAC => "AAC",
+        CODEC_TYPE_VORBIS => "Vorbis",
+        CODEC_TYPE_OPUS => "Opus",
+        CODEC_TYPE_SPEEX => "Speex",
+        CODEC_TYPE_MUSEPACK => "Musepack",
+        CODEC_TYPE_WMA => "WMA",
+        CODEC_TYPE_PCM_ALAW | CODEC_TYPE_PCM_MULAW => "G.711",
+        // The many PCM and ADPCM variants are told apart by their decoder's name, e.g. pcm_s16le
+        _ => {
+            let descriptor = symphonia::default::get_codecs().get_codec(codec)?;
+            if descriptor.short_name.starts_with("pcm_") {
+                "PCM"
+            } else if descriptor.short_name.starts_with("adpcm_") {
+                "ADPCM"
+            } else {
+                return None;
+            }
+        }
     };
+    Some(name)
+}
+
+fn read_stream_properties(path: &Path) -> Result<AudioProperties, SymphoniaError> {
+    let stream = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut format = probed.format;
+    let track = format
+        .default_track()
+        .filter(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or(SymphoniaError::Unsupported("no audio track"))?;
+    let track_id = track.id;
+    let params = track.codec_params.clone();
+    let time_base = params
+        .time_base
+        .or(params.sample_rate.map(|rate| TimeBase::new(1, rate)))
+        .ok_or(SymphoniaError::Unsupported("unknown sample rate"))?;
+
+    let frames = match params.n_frames {
+        Some(frames) => frames,
+        None => {
+            let mut decoder = symphonia::default::get_codecs().make(&params, &DecoderOptions::default())?;
+            let mut frames = 0;
+            loop {
+                let packet = match format.next_packet() {
+                    Ok(packet) => packet,
+                    Err(SymphoniaError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                    Err(e) => return Err(e),
+                };
+                if packet.track_id() != track_id {
+                    continue;
+                }
+
+                // Packets usually know their own length, only decode the ones that don't
+                frames += match packet.dur {
+                    0 => match decoder.decode(&packet) {
+                        Ok(decoded) => decoded.frames() as u64,
+                        Err(SymphoniaError::DecodeError(_)) => 0,
+                        Err(e) => return Err(e),
+                    },
+                    dur => dur,
+                };
+            }
+            frames
+        }
+    };
+
+    let time = time_base.calc_time(frames);
+    let codec = codec_name(params.codec);
+
+    Ok(AudioProperties {
+        duration: time.seconds * 1000 + (time.frac * 1000.0).round() as u64,
+        codec: codec.map(String::from),
+        container: None,
+        sample_rate: params.sample_rate,
+        // Lossy codecs have no bit depth of their own
+        bit_depth: params.bits_per_sample.filter(|_| codec.is_some_and(|codec| LOSSLESS_CODECS.contains(&codec))),
+        channels: params.channels.map(|channels| channels.count() as u32),
+        bitrate: None,
+        lossless: codec.is_some_and(|codec| LOSSLESS_CODECS.contains(&codec)),
+    })
+}
+
+fn read_dsd_properties(path: &Path) -> Result<Option<AudioProperties>, Box<dyn Error>> {
+    let mut data = Vec::new();
+    File::open(path)?.take(64 * 1024).read_to_end(&mut data)?;
+    let bytes = |start: usize, length: usize| data.get(start..start + length).ok_or("DSD header is truncated");
+
+    let (container, sample_rate, channels, frames) = match data.get(0..4) {
+        Some(b"DSD ") => {
+            let u32_at = |start| bytes(start, 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+            let frames = u64::from_le_bytes(bytes(64, 8)?.try_into().unwrap());
+            ("DSF", u32_at(56)?, u32_at(52)?, frames)
+        }
+        Some(b"FRM8") => {
+            let (mut sample_rate, mut channels, mut frames) = (0, 0, 0);
+            let mut position = 16;
+            while let Ok(id) = bytes(position, 4) {
+                let size = u64::from_be_bytes(bytes(position + 4, 8)?.try_into().unwrap()) as usize;
+                let body = position + 12;
+                match id {
+                    // Property chunks hold "SND " followed by their own sub chunks
+                    b"PROP" => {
+                        position = body + 4;
+                        continue;
+                    }
+                    b"FS  " => sample_rate = u32::from_be_bytes(bytes(body, 4)?.try_into().unwrap()),
+                    b"CHNL" => channels = u16::from_be_bytes(bytes(body, 2)?.try_into().unwrap()) as u32,
+                    b"DSD " => frames = size as u64 * 8 / channels.max(1) as u64,
+                    b"DST " => return Err("Unsupported codec: DST compressed DSD".into()),
+                    _ => {}
+                }
+                position = body + size + size % 2;
+            }
+            ("DSDIFF", sample_rate, channels, frames)
+        }
+        _ => return Ok(None),
+    };
+
+    if sample_rate == 0 || channels == 0 {
+        return Err("DSD header is missing its sample rate or channels".into());
+    }
+
+    Ok(Some(AudioProperties {
+        duration: frames * 1000 / sample_rate as u64,
+        codec: Some("DSD".into()),
+        container: Some(container.into()),
+        sample_rate: Some(sample_rate),
+        bit_depth: Some(1),
+        channels: Some(channels),
+        bitrate: Some(sample_rate * channels / 1000),
+        lossless: true,
+    }))
+}
+
+pub fn read_properties(file_path: &str) -> Result<AudioProperties, Box<dyn Error>> {
+    let path = Path::new(file_path);
+    let info = tags::read_file_info(path);
+
+    match (read_stream_properties(path), info) {
+        (Ok(mut properties), Ok(info)) => {
+            properties.container = Some(info.container.into());
+            properties.bitrate = info.bitrate;
+            Ok(properties)
+        }
+        (Ok(properties), Err(_)) => Ok(properties),
+        (Err(_), Ok(info)) => {
+            let lossless = info.codec.is_some_and(|codec| LOSSLESS_CODECS.contains(&codec));
+            Ok(AudioProperties {
+                duration: info.duration.as_millis() as u64,
+                codec: info.codec.map(String::from),
+                container: Some(info.container.into()),
+                sample_rate: info.sample_rate,
+                bit_depth: info.bit_depth.map(u32::from).filter(|_| lossless),
+                channels: info.channels.map(u32::from),
+                bitrate: info.bitrate,
+                lossless,
+            })
+        }
+        (Err(e), Err(_)) => match read_dsd_properties(path)? {
+            Some(properties) => Ok(properties),
+            None => match e {
+                SymphoniaError::Unsupported(_) => Err(format!("Unsupported codec: {} can't be read", file_path).into()),
+                e => Err(e.into()),
+            },
+        },
+    }
 }
 
 #[tauri::command]
-pub fn play(file_path: String, state: tauri::State<MusicPlayer>) -> Result<String, String> {
+pub fn play(file_path: String, state: tauri::State<MusicPlayer>, app: tauri::AppHandle) -> Result<String, String> {
     let source = get_source(file_path.as_str()).map_err(|e| e.to_string())?;
+
+    finish_now_playing(&state, app.clone());
     state.sink.clear();
-    state.sink.append(source);
+    append_song(&state, file_path, source, app);
     state.sink.play();
     Ok("success".to_string())
 }
 
 #[tauri::command]
-pub fn add_to_queue(file_path: String, state: tauri::State<MusicPlayer>) -> Result<String, String> {
+pub fn add_to_queue(file_path: String, state: tauri::State<MusicPlayer>, app: tauri::AppHandle) -> Result<String, String> {
     let source = get_source(file_path.as_str()).map_err(|e| e.to_string())?;
-    state.sink.append(source);
+    append_song(&state, file_path, source, app);
     Ok("success".to_string())
 }
 
 #[tauri::command]
 pub fn pause(state: tauri::State<MusicPlayer>) {
     state.sink.pause();
+    if let Some(now_playing) = state.now_playing.lock().unwrap().as_mut() {
+        now_playing.pause();
+    }
 }
 
 #[tauri::command]
 pub fn resume(state: tauri::State<MusicPlayer>) {
     state.sink.play();
+    if let Some(now_playing) = state.now_playing.lock().unwrap().as_mut() {
+        now_playing.resume();
+    }
 }
 
 #[tauri::command]
-pub fn stop(state: tauri::State<MusicPlayer>) {
+pub fn stop(state: tauri::State<MusicPlayer>, app: tauri::AppHandle) {
+    finish_now_playing(&state, app);
     state.sink.stop();
 }
 
@@ -64,7 +416,8 @@ pub fn seek(position: String, state: tauri::State<MusicPlayer>) -> String {
 }
 
 #[tauri::command]
-pub fn skip_forward(state: tauri::State<MusicPlayer>) {
+pub fn skip_forward(state: tauri::State<MusicPlayer>, app: tauri::AppHandle) {
+    finish_now_playing(&state, app);
     state.sink.skip_one();
 }
 
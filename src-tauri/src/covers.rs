@@ -0,0 +1,387 @@
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use image::{codecs::{jpeg::JpegEncoder, webp::WebPEncoder}, imageops::FilterType, DynamicImage};
+use lofty::{config::ParseOptions, file::TaggedFileExt, picture::PictureType, probe::Probe};
+use tauri::Manager;
+
+pub(crate) const COVER_PATTERNS_KEY: &str = "cover_patterns";
+
+pub(crate) const DEFAULT_COVER_PATTERNS: [&str; 10] = [
+    "front",
+    "cover",
+    "folder",
+    "{album}",
+    "albumart_*_large",
+    "albumartlarge",
+    "*front*",
+    "*cover*",
+    "*folder*",
+    "albumart*",
+];
+
+const SECONDARY_ARTWORK: [&str; 14] = [
+    "back", "rear", "cd", "disc", "disk", "booklet", "inlay", "inside", "inner", "tray", "spine", "obi", "label", "matrix",
+];
+
+const ARTWORK_FOLDERS: [&str; 7] = ["scans", "scan", "artwork", "art", "covers", "images", "booklet"];
+
+const IMAGE_EXTENSIONS: [&str; 6] = ["jpg", "jpeg", "png", "gif", "bmp", "webp"];
+
+#[derive(Debug)]
+struct CoverCandidate {
+    path: PathBuf,
+    rank: usize,
+    secondary: bool,
+    in_subfolder: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GlobToken {
+    Any,
+    One,
+    Literal(char),
+}
+
+fn compile_pattern(pattern: &str, album_title: &str) -> Vec<GlobToken> {
+    let mut tokens = Vec::new();
+    for (i, part) in pattern.to_lowercase().split("{album}").enumerate() {
+        if i > 0 {
+            tokens.extend(album_title.to_lowercase().chars().map(GlobToken::Literal));
+        }
+        tokens.extend(part.chars().map(|c| match c {
+            '*' => GlobToken::Any,
+            '?' => GlobToken::One,
+            c => GlobToken::Literal(c),
+        }));
+    }
+    tokens
+}
+
+fn glob_matches(pattern: &[GlobToken], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(GlobToken::Any), _) => glob_matches(&pattern[1..], name) || (!name.is_empty() && glob_matches(pattern, &name[1..])),
+        (Some(GlobToken::One), Some(_)) => glob_matches(&pattern[1..], &name[1..]),
+        (Some(GlobToken::Literal(p)), Some(n)) if p == n => glob_matches(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+fn is_secondary(stem: &str) -> bool {
+    stem.split(|c: char| !c.is_alphanumeric())
+        .map(|word| word.trim_end_matches(|c: char| c.is_ascii_digit()))
+        .any(|word| SECONDARY_ARTWORK.contains(&word))
+}
+
+fn collect_candidates(dir: &Path, album_title: &str, patterns: &[String], in_subfolder: bool, candidates: &mut Vec<CoverCandidate>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    let compiled = patterns.iter().map(|pattern| compile_pattern(pattern, album_title)).collect::<Vec<_>>();
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+
+        let extension = path.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+        if !IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+            continue;
+        }
+
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_lowercase();
+        let stem = stem.chars().collect::<Vec<_>>();
+        if let Some(rank) = compiled.iter().position(|pattern| glob_matches(pattern, &stem)) {
+            // A title like "Back in Black" shouldn't demote the album's own cover
+            let secondary = !patterns[rank].contains("{album}") && is_secondary(&stem.iter().collect::<String>());
+            candidates.push(CoverCandidate { path, rank, secondary, in_subfolder });
+        }
+    }
+}
+
+pub(crate) fn find_cover_art(dir: &Path, album_title: &str, patterns: &[String]) -> Option<String> {
+    let mut candidates = Vec::new();
+    collect_candidates(dir, album_title, patterns, false, &mut candidates);
+
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let name = entry.file_name().to_string_lossy().to_lowercase();
+        if entry.path().is_dir() && ARTWORK_FOLDERS.contains(&name.as_str()) {
+            collect_candidates(&entry.path(), album_title, patterns, true, &mut candidates);
+        }
+    }
+
+    let best = candidates
+        .iter()
+        .map(|c| (c.secondary, c.rank, c.in_subfolder))
+        .min()?;
+    let area = |path: &Path| image::image_dimensions(path).map(|(w, h)| w as u64 * h as u64).unwrap_or(0);
+
+    candidates
+        .into_iter()
+        .filter(|c| (c.secondary, c.rank, c.in_subfolder) == best)
+        .max_by_key(|c| area(&c.path))
+        .map(|c| c.path.to_string_lossy().to_string())
+}
+
+pub(crate) fn find_artist_image(dir: &Path, artist: &str) -> Option<String> {
+    let dir_name = dir.file_name()?.to_string_lossy().trim().to_lowercase();
+    let named_after_artist = dir_name == artist.trim().to_lowercase();
+
+    let mut images = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            let extension = path.extension()?.to_string_lossy().to_lowercase();
+            if !IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+                return None;
+            }
+
+            match path.file_stem()?.to_string_lossy().to_lowercase().as_str() {
+                "artist" => Some((0, path)),
+                "folder" if named_after_artist => Some((1, path)),
+                _ => None,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    images.sort();
+    images.into_iter().next().map(|(_, path)| path.to_string_lossy().to_string())
+}
+
+pub(crate) fn cover_cache_dir(app: &tauri::AppHandle) -> Result<PathBuf, Box<dyn Error>> {
+    let dir = app.path().app_data_dir()?.join("covers");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+// Picks an extension from the image's magic bytes, since the MIME type in tags is often wrong or missing
+fn image_extension(data: &[u8]) -> Option<&'static str> {
+    match data {
+        [0xFF, 0xD8, 0xFF, ..] => Some("jpg"),
+        [0x89, b'P', b'N', b'G', ..] => Some("png"),
+        [b'G', b'I', b'F', b'8', ..] => Some("gif"),
+        [b'B', b'M', ..] => Some("bmp"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("webp"),
+        _ => None,
+    }
+}
+
+pub(crate) fn extract_embedded_cover(song_path: &Path, cache_dir: &Path) -> Result<Option<String>, Box<dyn Error>> {
+    let tagged_file = Probe::open(song_path)?
+        .options(ParseOptions::new().read_properties(false))
+        .read()?;
+
+    let pictures = tagged_file
+        .tags()
+        .iter()
+        .flat_map(|tag| tag.pictures())
+        .filter(|picture| image_extension(picture.data()).is_some())
+        .collect::<Vec<_>>();
+    let picture = pictures
+        .iter()
+        .find(|picture| picture.pic_type() == PictureType::CoverFront)
+        .or(pictures.first());
+    let Some(picture) = picture else { return Ok(None) };
+
+    let data = picture.data();
+    let extension = image_extension(data).unwrap_or("jpg");
+    let cover_path = cache_dir.join(format!("{:x}.{}", md5::compute(data), extension));
+
+    if !cover_path.exists() {
+        fs::write(&cover_path, data)?;
+    }
+
+    Ok(Some(cover_path.to_string_lossy().to_string()))
+}
+
+const MAX_EMBEDDED_SIZE: u32 = 1000;
+const MAX_EMBEDDED_BYTES: usize = 512 * 1024;
+const EMBEDDED_QUALITY: u8 = 90;
+
+fn encode_jpeg(image: DynamicImage, quality: u8) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut data = Vec::new();
+    image.into_rgb8().write_with_encoder(JpegEncoder::new_with_quality(&mut data, quality))?;
+    Ok(data)
+}
+
+pub(crate) fn prepare_embedded_cover(source: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    let data = fs::read(source)?;
+    let image = image::load_from_memory(&data)?;
+    let too_large = image.width() > MAX_EMBEDDED_SIZE || image.height() > MAX_EMBEDDED_SIZE;
+
+    if !too_large && data.len() <= MAX_EMBEDDED_BYTES && matches!(image_extension(&data), Some("jpg" | "png")) {
+        return Ok(data);
+    }
+
+    let image = if too_large {
+        image.resize(MAX_EMBEDDED_SIZE, MAX_EMBEDDED_SIZE, FilterType::Lanczos3)
+    } else {
+        image
+    };
+
+    encode_jpeg(image, EMBEDDED_QUALITY)
+}
+
+pub(crate) fn copy_cover_to_folder(source: &Path, album_dir: &Path) -> Result<String, Box<dyn Error>> {
+    let destination = album_dir.join("cover.jpg");
+    if destination == source {
+        return Ok(destination.to_string_lossy().to_string());
+    }
+
+    let data = fs::read(source)?;
+    if image_extension(&data) == Some("jpg") {
+        fs::write(&destination, data)?;
+    } else {
+        fs::write(&destination, encode_jpeg(image::load_from_memory(&data)?, EMBEDDED_QUALITY)?)?;
+    }
+
+    Ok(destination.to_string_lossy().to_string())
+}
+
+const THUMBNAIL_SIZES: [u32; 3] = [128, 256, 512];
+const THUMBNAIL_QUALITY: u8 = 85;
+
+pub(crate) fn thumbnail_cache_dir(app: &tauri::AppHandle) -> Result<PathBuf, Box<dyn Error>> {
+    let dir = app.path().app_data_dir()?.join("thumbnails");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn thumbnail_size(requested: u32) -> u32 {
+    THUMBNAIL_SIZES
+        .into_iter()
+        .find(|size| *size >= requested)
+        .unwrap_or(THUMBNAIL_SIZES[THUMBNAIL_SIZES.len() - 1])
+}
+
+fn is_fresh(thumbnail: &Path, source: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+
+    match (modified(thumbnail), modified(source)) {
+        (Some(thumbnail), Some(source)) => thumbnail >= source,
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbnailFormat {
+    Jpeg,
+    Webp,
+}
+
+impl ThumbnailFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ThumbnailFormat::Jpeg => "jpg",
+            ThumbnailFormat::Webp => "webp",
+        }
+    }
+}
+
+static THUMBNAIL_WRITES: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn get_thumbnail(
+    source: &Path,
+    size: u32,
+    format: Option<ThumbnailFormat>,
+    cache_dir: &Path,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let size = thumbnail_size(size);
+    let key = format!("{:x}", md5::compute(source.to_string_lossy().as_bytes()));
+    let thumbnail = |format: ThumbnailFormat| cache_dir.join(format!("{}_{}.{}", key, size, format.extension()));
+
+    let formats = format.map_or(vec![ThumbnailFormat::Jpeg, ThumbnailFormat::Webp], |format| vec![format]);
+    if let Some(fresh) = formats.into_iter().map(thumbnail).find(|thumbnail| is_fresh(thumbnail, source)) {
+        return Ok(fresh);
+    }
+
+    // Only the header is read here, small covers are never decoded
+    let (width, height) = image::image_dimensions(source)?;
+    if width <= size && height <= size {
+        return Ok(source.to_path_buf());
+    }
+
+    let image = image::open(source)?;
+    let format = format.unwrap_or(if image.color().has_alpha() { ThumbnailFormat::Webp } else { ThumbnailFormat::Jpeg });
+    let resized = image.thumbnail(size, size);
+
+    let destination = thumbnail(format);
+    let temporary = cache_dir.join(format!(
+        "{}_{}.{}.{}.tmp",
+        key,
+        size,
+        std::process::id(),
+        THUMBNAIL_WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let written = File::create(&temporary).map_err(Box::<dyn Error>::from).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        match format {
+            // JPEG has no alpha channel, so covers with transparency are flattened
+            ThumbnailFormat::Jpeg => resized
+                .into_rgb8()
+                .write_with_encoder(JpegEncoder::new_with_quality(&mut writer, THUMBNAIL_QUALITY))?,
+            ThumbnailFormat::Webp => resized.into_rgba8().write_with_encoder(WebPEncoder::new_lossless(&mut writer))?,
+        }
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        Ok(fs::rename(&temporary, &destination)?)
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temporary);
+        return Err(e);
+    }
+
+    Ok(destination)
+}
+
+#[tauri::command]
+pub async fn get_cover_thumbnail(
+    cover_path: PathBuf,
+    size: u32,
+    format: Option<ThumbnailFormat>,
+    app: tauri::AppHandle,
+) -> Result<String, String> {
+    let cache_dir = thumbnail_cache_dir(&app).map_err(|e| e.to_string())?;
+
+    let thumbnail = tauri::async_runtime::spawn_blocking(move || {
+        get_thumbnail(&cover_path, size, format, &cache_dir).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    Ok(thumbnail.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, album_title: &str, name: &str) -> bool {
+        glob_matches(&compile_pattern(pattern, album_title), &name.chars().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn wildcards_match_file_names() {
+        assert!(matches("*cover*", "", "my cover 2"));
+        assert!(matches("albumart_*_large", "", "albumart_{1234}_large"));
+        assert!(matches("cd?", "", "cd1"));
+        assert!(!matches("cd?", "", "cd"));
+        assert!(!matches("front", "", "front 2"));
+    }
+
+    #[test]
+    fn album_titles_are_matched_literally() {
+        assert!(matches("{album}", "Greatest Hits *", "greatest hits *"));
+        assert!(!matches("{album}", "Greatest Hits *", "greatest hits back"));
+        assert!(!matches("{album}", "Why?", "whyx"));
+        assert!(matches("{album} - *", "[Brackets]", "[brackets] - front"));
+    }
+}
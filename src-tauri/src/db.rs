@@ -1,25 +1,62 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     error::Error,
     path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
 };
 
-use audiotags::Tag;
 use jwalk::WalkDir;
-use rusqlite::{params, Connection, Params};
+use lofty::tag::{Accessor, ItemKey, Tag as LoftyTag, TagType};
+use rusqlite::{params, Connection, OptionalExtension, Params};
 use serde_json::Value;
 use tauri::Manager;
 
-use crate::audio;
+use crate::artists::{self, ArtistSplitter};
+use crate::audio::{self, AudioProperties};
+use crate::covers;
+use crate::edit_history;
+use crate::settings;
+use crate::tags::{self, ArtistTags, ExtendedTags, MusicBrainzIds};
+
+const VARIOUS_ARTISTS: &str = "Various Artists";
+const VARIOUS_ARTISTS_ALIASES: [&str; 5] = ["various artists", "various", "va", "v.a.", "v/a"];
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct AlbumKey {
+    artist: String,
+    title: String,
+    release: String,
+}
+
+impl AlbumKey {
+    fn new(song: &SongMetadata) -> Self {
+        // Songs without an album artist are grouped regardless of artist, see `resolve_album_artist`
+        let artist = match (song.album_artist_tagged, song.compilation) {
+            (true, _) => song.album_artist.to_lowercase(),
+            (false, true) => VARIOUS_ARTISTS.to_lowercase(),
+            (false, false) => String::new(),
+        };
+
+        AlbumKey {
+            artist,
+            title: song.album_title.to_lowercase(),
+            release: song.musicbrainz_ids.release.clone().unwrap_or_else(|| song.album_dir.clone()),
+        }
+    }
+}
 
 #[derive(Debug)]
 struct AlbumMetadata {
     location_on_disk: String,
     cover_path: Option<String>,
+    artist_image_path: Option<String>,
     title: String,
     artist: String,
     year: i32,
     genre: String,
+    release_mbid: Option<String>,
+    compilation: bool,
     songs: Vec<SongMetadata>,
 }
 
@@ -28,10 +65,13 @@ impl Clone for AlbumMetadata {
         AlbumMetadata {
             location_on_disk: self.location_on_disk.clone(),
             cover_path: self.cover_path.clone(),
+            artist_image_path: self.artist_image_path.clone(),
             title: self.title.clone(),
             artist: self.artist.clone(),
             year: self.year,
             genre: self.genre.clone(),
+            release_mbid: self.release_mbid.clone(),
+            compilation: self.compilation,
             songs: self.songs.clone(),
         }
     }
@@ -40,55 +80,176 @@ impl Clone for AlbumMetadata {
 #[derive(Debug)]
 struct SongMetadata {
     parent_dir: String,
+    album_dir: String,
     file_path: String,
     title: String,
     artist: String,
     album_title: String,
     album_artist: String,
+    album_artist_tagged: bool,
     track_number: u16,
     disc_number: u16,
-    duration: u64,
+    disc_total: u16,
+    disc_subtitle: Option<String>,
+    properties: AudioProperties,
     year: i32,
     genre: String,
+    rating: Option<u8>,
+    artist_tags: ArtistTags,
+    extended_tags: ExtendedTags,
+    musicbrainz_ids: MusicBrainzIds,
+    compilation: bool,
 }
 
 impl Clone for SongMetadata {
     fn clone(&self) -> Self {
         SongMetadata {
             parent_dir: self.parent_dir.clone(),
+            album_dir: self.album_dir.clone(),
             file_path: self.file_path.clone(),
             title: self.title.clone(),
             artist: self.artist.clone(),
             album_title: self.album_title.clone(),
             album_artist: self.album_artist.clone(),
+            album_artist_tagged: self.album_artist_tagged,
             track_number: self.track_number,
             disc_number: self.disc_number,
-            duration: self.duration,
+            disc_total: self.disc_total,
+            disc_subtitle: self.disc_subtitle.clone(),
+            properties: self.properties.clone(),
             year: self.year,
             genre: self.genre.clone(),
+            rating: self.rating,
+            artist_tags: self.artist_tags.clone(),
+            extended_tags: self.extended_tags.clone(),
+            musicbrainz_ids: self.musicbrainz_ids.clone(),
+            compilation: self.compilation,
         }
     }
 }
 
-fn get_db_connection(app: tauri::AppHandle) -> Result<Connection, Box<dyn Error>> {
+pub(crate) fn get_db_connection(app: tauri::AppHandle) -> Result<Connection, Box<dyn Error>> {
     let local_data_dir = app.path().app_data_dir()?;
     let db_path = local_data_dir.join("music.db");
 
     Ok(Connection::open(db_path)?)
 }
 
+// Columns added to tables after they were first created. `CREATE TABLE IF NOT EXISTS` leaves
+// existing tables alone, so these are added to older databases before the schema runs
+const ADDED_COLUMNS: [(&str, &str, &str); 33] = [
+    ("song", "album_id", "INTEGER"),
+    ("song", "disc_total", "INTEGER DEFAULT 0"),
+    ("song", "disc_subtitle", "TEXT"),
+    ("song", "codec", "TEXT"),
+    ("song", "container", "TEXT"),
+    ("song", "sample_rate", "INTEGER"),
+    ("song", "bit_depth", "INTEGER"),
+    ("song", "channels", "INTEGER"),
+    ("song", "bitrate", "INTEGER"),
+    ("song", "lossless", "INTEGER NOT NULL DEFAULT 0"),
+    ("song", "composer", "TEXT"),
+    ("song", "conductor", "TEXT"),
+    ("song", "lyricist", "TEXT"),
+    ("song", "bpm", "INTEGER"),
+    ("song", "initial_key", "TEXT"),
+    ("song", "mood", "TEXT"),
+    ("song", "comment", "TEXT"),
+    ("song", "label", "TEXT"),
+    ("song", "catalog_number", "TEXT"),
+    ("song", "isrc", "TEXT"),
+    ("song", "barcode", "TEXT"),
+    ("song", "original_year", "INTEGER"),
+    ("song", "release_date", "TEXT"),
+    ("song", "title_sort", "TEXT"),
+    ("song", "artist_sort", "TEXT"),
+    ("song", "album_sort", "TEXT"),
+    ("song", "album_artist_sort", "TEXT"),
+    ("song", "recording_mbid", "TEXT"),
+    ("song", "track_mbid", "TEXT"),
+    ("song", "release_mbid", "TEXT"),
+    ("song", "release_group_mbid", "TEXT"),
+    ("song", "artist_mbids", "TEXT"),
+    ("song", "album_artist_mbids", "TEXT"),
+];
+
+// Stored in `PRAGMA user_version`. Version 1 stores durations in milliseconds instead of seconds
+const SCHEMA_VERSION: i64 = 1;
+
+fn join_ids(ids: &[String]) -> Option<String> {
+    Some(ids.join(",")).filter(|ids| !ids.is_empty())
+}
+
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<(String, bool)>, rusqlite::Error> {
+    conn.prepare(&format!("PRAGMA table_info({})", table))?
+        .query_map([], |row| Ok((row.get::<_, String>("name")?, row.get::<_, i64>("pk")? > 0)))?
+        .collect()
+}
+
+pub(crate) fn init_database(conn: &mut Connection, schema: &str) -> Result<(), rusqlite::Error> {
+    for (table, column, definition) in ADDED_COLUMNS {
+        let columns = table_columns(conn, table)?;
+        if !columns.is_empty() && !columns.iter().any(|(name, _)| name == column) {
+            conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+        }
+    }
+
+    let keyed_by_folder = table_columns(conn, "album")?
+        .iter()
+        .any(|(name, pk)| name == "location_on_disk" && *pk);
+    if keyed_by_folder {
+        conn.execute_batch(
+            "DROP INDEX IF EXISTS idx_album_artist;
+            ALTER TABLE album RENAME TO album_by_folder;",
+        )?;
+    }
+
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    conn.execute_batch(schema)?;
+
+    let tx = conn.transaction()?;
+    if version < 1 {
+        tx.execute_batch(
+            "UPDATE song SET duration = duration * 1000;
+            UPDATE scrobble_queue SET duration = duration * 1000;",
+        )?;
+    }
+    if keyed_by_folder {
+        tx.execute_batch(
+            "INSERT INTO album (location_on_disk, cover_path, title, artist, year, genre)
+            SELECT location_on_disk, cover_path, title, artist, year, genre FROM album_by_folder;
+            DROP TABLE album_by_folder;",
+        )?;
+    }
+    // Songs scanned before albums had an id are linked by title and artist until they are rescanned
+    tx.execute(
+        "UPDATE song SET album_id = (
+            SELECT id FROM album WHERE album.title = song.album_title AND album.artist = song.album_artist ORDER BY id LIMIT 1
+        ) WHERE album_id IS NULL",
+        [],
+    )?;
+    tx.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))?;
+    tx.commit()
+}
+
 fn is_audio_file(path: &Path) -> bool {
     if !path.is_file() {
         return false;
     }
 
-    path.extension()
+    let extension = path
+        .extension()
         .and_then(|ext| ext.to_str())
-        .map(|ext| match ext {
-            "mp3" | "flac" | "m4a" | "ogg" | "wav" => true,
-            _ => false,
-        })
-        .unwrap_or_default()
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        ext if audio::AUDIO_EXTENSIONS.contains(&ext) => true,
+        "jpg" | "jpeg" | "png" | "gif" | "webp" | "bmp" | "txt" | "nfo" | "log" | "cue" | "m3u" | "m3u8" | "pls"
+        | "xspf" | "pdf" | "lrc" | "sfv" | "md5" | "db" | "ini" => false,
+        _ => audio::has_audio_signature(path),
+    }
 }
 
 fn get_song_count(dir: &Path) -> i32 {
@@ -108,59 +269,57 @@ fn get_song_count(dir: &Path) -> i32 {
     return count;
 }
 
-fn find_cover_art(dir: &Path, album_title: &str) -> Option<String> {
-    println!("Searching for cover art in {}", dir.to_string_lossy());
-    for entry in WalkDir::new(dir) {
-        let Ok(entry) = entry else { continue };
-        if !entry.file_type.is_file() {
-            continue;
-        }
+fn disc_folder(dir: &Path) -> Option<(u16, Option<String>)> {
+    let name = dir.file_name()?.to_string_lossy().trim().to_string();
+    let lowered = name.to_ascii_lowercase();
 
-        let valid_extensions = ["jpg", "png", "jpeg", "gif", "bmp", "webp"];
-        if let Some(extension) = entry.path().extension() {
-            if !valid_extensions.contains(&extension.to_str().unwrap_or_default()) {
-                continue;
-            }
-        }
+    let prefix = ["cd", "disc", "disk"].into_iter().find(|prefix| lowered.starts_with(prefix))?;
+    let rest = name[prefix.len()..].trim_start_matches([' ', '_', '-', '.']);
+    let digits = rest.chars().take_while(char::is_ascii_digit).count();
+    let number = rest[..digits].parse::<u16>().ok()?;
 
-        let lowercase = entry
-            .path()
-            .file_stem()
-            .unwrap_or_default()
-            .to_ascii_lowercase();
-        let name = lowercase.to_str().unwrap_or_default();
-        let cover_keywords = [
-            "cover",
-            "folder",
-            "front",
-            &album_title.to_ascii_lowercase(),
-        ];
-
-        if cover_keywords.contains(&name) {
-            return Some(entry.path().to_string_lossy().to_string());
-        }
+    let remainder = &rest[digits..];
+    if remainder.starts_with(|c: char| c.is_alphanumeric()) {
+        return None;
     }
 
-    return None;
+    let subtitle = remainder.trim_matches(|c: char| c.is_whitespace() || "-_:.()[]".contains(c));
+    let subtitle = if subtitle.is_empty() || subtitle.to_ascii_lowercase().starts_with("of ") {
+        None
+    } else {
+        Some(subtitle.to_string())
+    };
+
+    Some((number, subtitle))
 }
 
-fn get_song_metadata(path: &PathBuf) -> Result<SongMetadata, Box<dyn Error>> {
-    let file_path = path.clone().to_string_lossy().to_string();
+fn get_song_metadata(path: &Path) -> Result<SongMetadata, Box<dyn Error>> {
+    let file_path = path.to_string_lossy().to_string();
     let file_name = path.file_name().ok_or("Failed to get file name")?;
     let file_name = file_name.to_string_lossy().to_string();
     let parent_dir = path.parent().ok_or("Failed to get parent directory")?;
 
-    let tag = Tag::new().read_from_path(&path)?;
-    let title = tag.title().unwrap_or(&file_name).to_owned();
-    let artist = tag.artist().unwrap_or("Unknown").to_owned();
-    let album_title = tag.album_title().unwrap_or("Unknown").to_owned();
-    let album_artist = match tag.album_artist() {
-        Some(album_artist) => album_artist.to_owned(),
-        None => artist.clone(),
+    let properties = audio::read_properties(&file_path)?;
+    // Formats lofty can't read, e.g. DSD, are listed by their file name
+    let tag = tags::read_tag(path).unwrap_or_else(|_| LoftyTag::new(TagType::Id3v2));
+    let title = tag.title().map(String::from).unwrap_or(file_name.clone());
+    let artist = tag.artist().map(String::from).unwrap_or("Unknown".into());
+    let album_title = tag.album().map(String::from).unwrap_or("Unknown".into());
+    let album_artist_tagged = tag
+        .get_string(ItemKey::AlbumArtist)
+        .is_some_and(|album_artist| !album_artist.trim().is_empty());
+    let album_artist = match tag.get_string(ItemKey::AlbumArtist) {
+        Some(album_artist) if album_artist_tagged => album_artist.to_owned(),
+        _ => artist.clone(),
+    };
+    let album_artist = if VARIOUS_ARTISTS_ALIASES.contains(&album_artist.trim().to_lowercase().as_str()) {
+        VARIOUS_ARTISTS.to_string()
+    } else {
+        album_artist
     };
 
-    let track_number = match tag.track_number() {
-        Some(t) => t,
+    let track_number = match tag.track() {
+        Some(t) => t as u16,
         None => {
             // Attempt to get track number from first 2 characters in the file path
             let first_two_chars = file_name.clone().chars().take(2).collect::<String>();
@@ -171,81 +330,205 @@ fn get_song_metadata(path: &PathBuf) -> Result<SongMetadata, Box<dyn Error>> {
         },
     };
 
-    let disc_number = tag.disc_number().unwrap_or(0);
-    let duration = match tag.duration() {
-        Some(d) => {
-            // Attempt to convert milliseconds to seconds (PROBABLY NOT RELIABLE)
-            // If the duration divided by 1000 is less than 10, assume it's in seconds
-            let seconds = (d as u64) / 1000;
-
-            if seconds < 10 {
-                d as u64
-            } else {
-                seconds
-            }
-        }
-        None => audio::get_duration(&file_path)?,
+    // Discs in their own folder belong to the album in the folder above
+    let disc_folder = disc_folder(parent_dir);
+    let album_dir = match disc_folder {
+        Some(_) => parent_dir.parent().unwrap_or(parent_dir),
+        None => parent_dir,
     };
-
-    let year = tag.year().unwrap_or(0);
+    let disc_number = tag
+        .disk()
+        .map(|disc| disc as u16)
+        .filter(|disc| *disc > 0)
+        .or(disc_folder.as_ref().map(|(disc, _)| *disc))
+        .unwrap_or(0);
+    let disc_total = tag.disk_total().unwrap_or(0) as u16;
+
+    let year = tag.date().map(|date| date.year as i32).unwrap_or(0);
     let genre = tag.genre().unwrap_or_default().to_string();
+    let native_tag = tags::read_native_tag(path).ok();
+    let rating = native_tag.as_ref().and_then(|tag| tag.rating());
+    let artist_tags = native_tag.as_ref().map(|tag| tag.artist_tags()).unwrap_or_default();
+    let extended_tags = native_tag.as_ref().map(|tag| tag.extended_tags()).unwrap_or_default();
+    let musicbrainz_ids = native_tag.as_ref().map(|tag| tag.musicbrainz_ids()).unwrap_or_default();
+    let disc_subtitle = native_tag
+        .as_ref()
+        .and_then(|tag| tag.disc_subtitle())
+        .or(disc_folder.and_then(|(_, subtitle)| subtitle));
+    let compilation = album_artist == VARIOUS_ARTISTS || native_tag.is_some_and(|tag| tag.is_compilation());
 
     return Ok(SongMetadata {
         parent_dir: parent_dir.to_string_lossy().to_string(),
+        album_dir: album_dir.to_string_lossy().to_string(),
         file_path,
         title,
         artist,
         album_title,
         album_artist,
+        album_artist_tagged,
         track_number,
         disc_number,
-        duration,
+        disc_total,
+        disc_subtitle,
+        properties,
         year,
         genre,
+        rating,
+        artist_tags,
+        extended_tags,
+        musicbrainz_ids,
+        compilation,
     });
 }
 
-fn commit_to_db(albums: HashMap<String, AlbumMetadata>, app: tauri::AppHandle) -> Result<(), Box<dyn Error>> {
+fn resolve_album_artist(album: &mut AlbumMetadata, splitter: &ArtistSplitter) {
+    if album.songs.iter().all(|song| song.album_artist_tagged) {
+        return;
+    }
+
+    let main_artist = |song: &SongMetadata| {
+        splitter.main_artists(&song.artist).first().map(|artist| artist.to_lowercase())
+    };
+    let first_artist = album.songs.first().and_then(main_artist);
+    let single_artist = album.songs.iter().all(|song| main_artist(song) == first_artist);
+
+    album.artist = if album.compilation || !single_artist {
+        album.compilation = true;
+        VARIOUS_ARTISTS.to_string()
+    } else {
+        // The shortest credit leaves out featured artists, e.g. "A" rather than "A feat. B"
+        album.songs.iter().map(|song| song.artist.clone()).min_by_key(|artist| artist.len()).unwrap_or_default()
+    };
+
+    for song in album.songs.iter_mut().filter(|song| !song.album_artist_tagged) {
+        song.album_artist = album.artist.clone();
+    }
+}
+
+fn insert_song(
+    conn: &Connection,
+    song: &SongMetadata,
+    cover_path: &str,
+    album_id: i64,
+    splitter: &ArtistSplitter
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "INSERT OR REPLACE INTO song (file_path, cover_path, title, artist, album_title, album_artist, track_number, disc_number, disc_total, disc_subtitle, duration, year, genre, album_id,
+            codec, container, sample_rate, bit_depth, channels, bitrate, lossless,
+            composer, conductor, lyricist, bpm, initial_key, mood, comment, label, catalog_number, isrc, barcode, original_year, release_date,
+            title_sort, artist_sort, album_sort, album_artist_sort,
+            recording_mbid, track_mbid, release_mbid, release_group_mbid, artist_mbids, album_artist_mbids)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21,
+            ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30, ?31, ?32, ?33, ?34, ?35, ?36, ?37, ?38, ?39, ?40, ?41, ?42, ?43, ?44)",
+        params![
+            &song.file_path,
+            &cover_path,
+            &song.title,
+            &song.artist,
+            &song.album_title,
+            &song.album_artist,
+            &song.track_number,
+            &song.disc_number,
+            &song.disc_total,
+            &song.disc_subtitle,
+            &song.properties.duration,
+            &song.year,
+            &song.genre,
+            &album_id,
+            &song.properties.codec,
+            &song.properties.container,
+            &song.properties.sample_rate,
+            &song.properties.bit_depth,
+            &song.properties.channels,
+            &song.properties.bitrate,
+            &song.properties.lossless,
+            &song.extended_tags.composer,
+            &song.extended_tags.conductor,
+            &song.extended_tags.lyricist,
+            &song.extended_tags.bpm,
+            &song.extended_tags.initial_key,
+            &song.extended_tags.mood,
+            &song.extended_tags.comment,
+            &song.extended_tags.label,
+            &song.extended_tags.catalog_number,
+            &song.extended_tags.isrc,
+            &song.extended_tags.barcode,
+            &song.extended_tags.original_year,
+            &song.extended_tags.release_date,
+            &song.extended_tags.title_sort,
+            &song.extended_tags.artist_sort,
+            &song.extended_tags.album_sort,
+            &song.extended_tags.album_artist_sort,
+            &song.musicbrainz_ids.recording,
+            &song.musicbrainz_ids.track,
+            &song.musicbrainz_ids.release,
+            &song.musicbrainz_ids.release_group,
+            &join_ids(&song.musicbrainz_ids.artists),
+            &join_ids(&song.musicbrainz_ids.release_artists),
+        ]
+    )?;
+    let credits = splitter.credits(&song.artist_tags, &song.artist, &song.album_artist, &song.title);
+    artists::save_credits(conn, &song.file_path, &credits)?;
+
+    // Ratings from the file only fill in songs without one, so ones set in Sable survive a rescan
+    if let Some(rating) = song.rating {
+        conn.execute(
+            "INSERT INTO song_rating (file_path, rating) VALUES (?1, ?2)
+            ON CONFLICT (file_path) DO UPDATE SET rating = excluded.rating WHERE song_rating.rating = 0",
+            params![&song.file_path, &rating]
+        )?;
+    }
+
+    Ok(())
+}
+
+fn commit_to_db(albums: HashMap<AlbumKey, AlbumMetadata>, splitter: &ArtistSplitter, app: tauri::AppHandle) -> Result<(), Box<dyn Error>> {
     let mut conn = get_db_connection(app)?;
     let tx = conn.transaction()?;
 
     for (_, album) in albums {
         let cover_path = album.cover_path.clone().unwrap_or_default();
 
-        tx.execute(
-            "INSERT OR REPLACE INTO album (location_on_disk, cover_path, title, artist, year, genre) 
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        let album_id: i64 = tx.query_row(
+            "INSERT INTO album (location_on_disk, cover_path, title, artist, year, genre, release_mbid, compilation)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            ON CONFLICT (artist, title, location_on_disk) DO UPDATE SET
+                cover_path = excluded.cover_path,
+                year = excluded.year,
+                genre = excluded.genre,
+                release_mbid = excluded.release_mbid,
+                compilation = excluded.compilation
+            RETURNING id",
             params![
                 &album.location_on_disk,
                 &cover_path,
                 &album.title,
                 &album.artist,
                 &album.year,
-                &album.genre
-            ]
+                &album.genre,
+                &album.release_mbid,
+                &album.compilation
+            ],
+            |row| row.get(0)
         )?;
 
-        for song in album.songs {
+        // Images set in Sable are kept in custom_image_path, so a rescan never replaces them
+        if let Some(artist_image_path) = &album.artist_image_path {
             tx.execute(
-                "INSERT OR REPLACE INTO song (file_path, cover_path, title, artist, album_title, album_artist, track_number, disc_number, duration, year, genre)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
-                params![
-                    &song.file_path,
-                    &cover_path,
-                    &song.title,
-                    &song.artist,
-                    &song.album_title,
-                    &song.album_artist,
-                    &song.track_number,
-                    &song.disc_number,
-                    &song.duration,
-                    &song.year,
-                    &song.genre,
-                ]
+                "INSERT INTO artist (name, image_path) VALUES (?1, ?2)
+                ON CONFLICT (name) DO UPDATE SET image_path = excluded.image_path",
+                params![&album.artist, artist_image_path]
             )?;
         }
+
+        for song in album.songs {
+            insert_song(&tx, &song, &cover_path, album_id, splitter)?;
+        }
     }
 
+    // Songs that moved to another album, e.g. a compilation that was split by artist before, leave their old album empty
+    tx.execute("DELETE FROM album WHERE NOT EXISTS (SELECT 1 FROM song WHERE song.album_id = album.id)", [])?;
+
     tx.commit()?;
     return Ok(());
 }
@@ -263,18 +546,14 @@ pub async fn register_dir(dir: &Path, app: tauri::AppHandle) -> Result<String, S
     )
     .unwrap();
 
-    let mut albums = HashMap::new();
-    let mut current_album = AlbumMetadata {
-        location_on_disk: String::new(),
-        cover_path: None,
-        title: String::new(),
-        artist: String::new(),
-        year: 0,
-        genre: String::new(),
-        songs: Vec::new(),
-    };
+    let mut albums: HashMap<AlbumKey, AlbumMetadata> = HashMap::new();
     let mut successful = 0;
     let mut failed = 0;
+    let cover_cache_dir = covers::cover_cache_dir(&app).ok();
+    let conn = get_db_connection(app.clone()).map_err(|e| e.to_string())?;
+    let cover_patterns = settings::get_list_setting(&conn, covers::COVER_PATTERNS_KEY, &covers::DEFAULT_COVER_PATTERNS);
+    let splitter = ArtistSplitter::load(&conn);
+    drop(conn);
 
     for entry in WalkDir::new(&dir).sort(true) {
         let Ok(entry) = entry else { continue };
@@ -286,26 +565,37 @@ pub async fn register_dir(dir: &Path, app: tauri::AppHandle) -> Result<String, S
 
         match get_song_metadata(&song_path) {
             Ok(metadata) => {
-                if current_album.title != metadata.album_title || current_album.artist != metadata.album_artist {
-                    let location_on_disk = metadata.parent_dir.clone();
-                    let location_path = Path::new(&location_on_disk);
-                    let cover_path = find_cover_art(&location_path, &metadata.album_title);
-                    
-                    current_album = AlbumMetadata {
+                let album = albums.entry(AlbumKey::new(&metadata)).or_insert_with(|| {
+                    let location_on_disk = metadata.album_dir.clone();
+                    // Covers of multi-disc albums are usually next to the disc folders, sometimes inside them
+                    let cover_path = covers::find_cover_art(Path::new(&location_on_disk), &metadata.album_title, &cover_patterns)
+                        .or_else(|| covers::find_cover_art(Path::new(&metadata.parent_dir), &metadata.album_title, &cover_patterns));
+
+                    println!("Found album: {} by {}", metadata.album_title, metadata.album_artist);
+                    AlbumMetadata {
                         location_on_disk,
                         cover_path,
+                        artist_image_path: None,
                         title: metadata.album_title.clone(),
                         artist: metadata.album_artist.clone(),
-                        year: metadata.year.clone(),
+                        year: metadata.year,
                         genre: metadata.genre.clone(),
+                        release_mbid: metadata.musicbrainz_ids.release.clone(),
+                        compilation: false,
                         songs: Vec::new(),
-                    };
-
-                    albums.insert(current_album.title.clone(), current_album.clone());
-
-                    println!("Found album: {} by {}", current_album.title, current_album.artist);
+                    }
+                });
+                album.compilation |= metadata.compilation;
+                // Fall back to art embedded in the files when the folder has no cover image
+                if album.cover_path.is_none() {
+                    if let Some(cache_dir) = &cover_cache_dir {
+                        album.cover_path = covers::extract_embedded_cover(&song_path, cache_dir).unwrap_or_else(|e| {
+                            println!("Failed to extract cover from {}: {}", song_path.to_string_lossy(), e);
+                            None
+                        });
+                    }
                 }
-                albums.get_mut(&current_album.title).unwrap().songs.push(metadata.clone());
+                album.songs.push(metadata.clone());
                 successful += 1;
                 app.emit("song_registered", crate::Payload { message: song_path.to_string_lossy().to_string() }).unwrap();
             }
@@ -317,7 +607,24 @@ pub async fn register_dir(dir: &Path, app: tauri::AppHandle) -> Result<String, S
         }
     }
 
-    commit_to_db(albums, app).map_err(|e| e.to_string())?;
+    for album in albums.values_mut() {
+        resolve_album_artist(album, &splitter);
+
+        // Songs without a disc total take it from the highest disc number found
+        let disc_total = album.songs.iter().map(|song| song.disc_number.max(song.disc_total)).max().unwrap_or(0);
+        for song in album.songs.iter_mut().filter(|song| song.disc_total == 0) {
+            song.disc_total = disc_total;
+        }
+
+        // The folder above a compilation isn't an artist's folder
+        if !album.compilation {
+            album.artist_image_path = Path::new(&album.location_on_disk)
+                .parent()
+                .and_then(|artist_dir| covers::find_artist_image(artist_dir, &album.artist));
+        }
+    }
+
+    commit_to_db(albums, &splitter, app).map_err(|e| e.to_string())?;
 
     let mut message = format!("Registered {} songs", successful);
     if failed > 0 {
@@ -326,7 +633,34 @@ pub async fn register_dir(dir: &Path, app: tauri::AppHandle) -> Result<String, S
     Ok(message.into())
 }
 
-fn query_to_json<T: Params>(
+#[derive(Debug)]
+pub struct PlayEvent {
+    pub file_path: String,
+    pub started_at: u64,
+    pub listened_ms: u64,
+    pub completed: bool,
+    pub skipped: bool,
+}
+
+pub fn record_play_event(event: &PlayEvent, app: tauri::AppHandle) -> Result<(), Box<dyn Error>> {
+    let conn = get_db_connection(app)?;
+
+    conn.execute(
+        "INSERT INTO play_event (file_path, started_at, listened_ms, completed, skipped)
+        VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            &event.file_path,
+            &event.started_at,
+            &event.listened_ms,
+            &event.completed,
+            &event.skipped
+        ]
+    )?;
+
+    Ok(())
+}
+
+pub(crate) fn query_to_json<T: Params>(
     conn: &Connection,
     query: &str,
     params: T,
@@ -359,14 +693,14 @@ fn query_to_json<T: Params>(
     Ok(json)
 }
 
-fn query_row_params<T: Params>(query: &str, params: T, app: tauri::AppHandle) -> Result<String, String> {
+pub(crate) fn query_row_params<T: Params>(query: &str, params: T, app: tauri::AppHandle) -> Result<String, String> {
     let conn = get_db_connection(app).map_err(|e| e.to_string())?;
     let json = query_to_json(&conn, query, params).map_err(|e| e.to_string())?;
 
     Ok(json)
 }
 
-fn query_row(query: &str, app: tauri::AppHandle) -> Result<String, String> {
+pub(crate) fn query_row(query: &str, app: tauri::AppHandle) -> Result<String, String> {
     let conn = get_db_connection(app).map_err(|e| e.to_string())?;
     let json = query_to_json(&conn, query, params![]).map_err(|e| e.to_string())?;
 
@@ -380,8 +714,22 @@ pub fn get_all_albums(app: tauri::AppHandle) -> Result<String, String> {
 
 #[tauri::command]
 pub fn get_albums_by_artist(artist: String, app: tauri::AppHandle) -> Result<String, String> {
+    // Albums the artist only features on are listed after their own, flagged as appearances
     query_row_params(
-        "SELECT * FROM album WHERE artist = ?1 ORDER BY title",
+        "SELECT * FROM (
+            SELECT
+                album.*,
+                album.artist != ?1 AND NOT EXISTS (
+                    SELECT 1 FROM song JOIN song_artist ON song_artist.file_path = song.file_path
+                    WHERE song.album_id = album.id
+                        AND song_artist.artist = ?1 AND song_artist.role IN ('main', 'album_artist')
+                ) AS appearance
+            FROM album
+            WHERE album.artist = ?1 OR EXISTS (
+                SELECT 1 FROM song JOIN song_artist ON song_artist.file_path = song.file_path
+                WHERE song.album_id = album.id AND song_artist.artist = ?1
+            )
+        ) ORDER BY appearance, title",
         params![artist],
         app
     )
@@ -389,38 +737,260 @@ pub fn get_albums_by_artist(artist: String, app: tauri::AppHandle) -> Result<Str
 
 #[tauri::command]
 pub fn get_all_songs(app: tauri::AppHandle) -> Result<String, String> {
-    query_row("SELECT * FROM song ORDER BY album_artist, album_title, disc_number, track_number", app)
+    query_row("SELECT * FROM song_with_stats ORDER BY album_artist, album_title, disc_number, track_number", app)
 }
 
 #[tauri::command]
-pub fn get_songs_by_album(title: String, artist: String, app: tauri::AppHandle) -> Result<String, String> {
-    query_row_params("SELECT * FROM song WHERE album_title = ?1 AND album_artist = ?2 ORDER BY disc_number, track_number", params![title, artist], app)
+pub fn get_songs_by_album(title: String, artist: String, id: Option<i64>, app: tauri::AppHandle) -> Result<String, String> {
+    let songs = query_row_params(
+        "SELECT * FROM song_with_stats
+        WHERE album_id = ?3 OR (?3 IS NULL AND album_title = ?1 AND album_artist = ?2)
+        ORDER BY disc_number, track_number",
+        params![title, artist, id],
+        app
+    )?;
+    let songs = serde_json::from_str::<Vec<Value>>(&songs).map_err(|e| e.to_string())?;
+
+    let mut discs: Vec<Value> = Vec::new();
+    for song in songs {
+        let disc_number = song["disc_number"].clone();
+        match discs.last_mut() {
+            Some(disc) if disc["disc_number"] == disc_number => disc["songs"].as_array_mut().unwrap().push(song),
+            _ => discs.push(serde_json::json!({
+                "disc_number": disc_number,
+                "disc_subtitle": song["disc_subtitle"].clone(),
+                "songs": [song],
+            })),
+        }
+    }
+
+    Ok(Value::Array(discs).to_string())
 }
 
 #[tauri::command]
 pub fn get_all_artists(app: tauri::AppHandle) -> Result<String, String> {
     query_row(
-        "SELECT 
-            artist AS name, 
-            COUNT(*) AS album_count,
-            (SELECT COUNT(*) FROM song WHERE song.album_artist = album.artist) AS song_count
-        FROM album GROUP BY artist ORDER BY artist",
+        "SELECT
+            song_artist.artist AS name,
+            COUNT(DISTINCT CASE WHEN song_artist.role IN ('main', 'album_artist')
+                THEN song.album_id END) AS album_count,
+            COUNT(DISTINCT song.file_path) AS song_count,
+            COUNT(DISTINCT CASE WHEN song_artist.role IN ('featured', 'remixer') THEN song.file_path END) AS featured_count,
+            (SELECT COALESCE(custom_image_path, image_path) FROM artist WHERE artist.name = song_artist.artist) AS image_path,
+            -- Sort tags describe the whole field, so they only apply to artists credited alone
+            COALESCE(
+                MAX(CASE WHEN song_artist.role = 'main' AND song.artist = song_artist.artist THEN song.artist_sort END),
+                MAX(CASE WHEN song_artist.role = 'album_artist' AND song.album_artist = song_artist.artist THEN song.album_artist_sort END),
+                song_artist.artist
+            ) AS sort_name
+        FROM song_artist JOIN song ON song.file_path = song_artist.file_path
+        GROUP BY song_artist.artist ORDER BY sort_name COLLATE NOCASE, song_artist.artist",
         app
     )
 }
 
 #[tauri::command]
-pub fn remove_album(album: String, artist: String, app: tauri::AppHandle) -> Result<String, String> {
+pub fn set_artist_image(name: String, image_path: PathBuf, app: tauri::AppHandle) -> Result<String, String> {
+    // Only the header is read, to make sure it's an image Sable can display
+    image::image_dimensions(&image_path).map_err(|e| e.to_string())?;
+
     let conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO artist (name, custom_image_path) VALUES (?1, ?2)
+        ON CONFLICT (name) DO UPDATE SET custom_image_path = excluded.custom_image_path",
+        params![name, image_path.to_string_lossy()]
+    ).map_err(|e| e.to_string())?;
+
+    Ok("Artist image updated".into())
+}
 
+#[tauri::command]
+pub fn clear_artist_image(name: String, app: tauri::AppHandle) -> Result<String, String> {
+    let conn = get_db_connection(app).map_err(|e| e.to_string())?;
     conn.execute(
-        "DELETE FROM song WHERE album_title = ?1 AND album_artist = ?2",
-        params![album, artist],
+        "UPDATE artist SET custom_image_path = NULL WHERE name = ?1",
+        params![name]
+    ).map_err(|e| e.to_string())?;
+
+    Ok("Artist image cleared".into())
+}
+
+// Listening statistics. `from` and `to` are unix timestamps and default to all recorded history.
+// Plays are events that were listened to the end, listening time counts every event
+
+#[tauri::command]
+pub fn get_top_artists(from: Option<i64>, to: Option<i64>, limit: Option<i64>, app: tauri::AppHandle) -> Result<String, String> {
+    query_row_params(
+        "SELECT
+            song_artist.artist AS name,
+            SUM(play_event.completed) AS play_count,
+            SUM(play_event.listened_ms) AS listened_ms
+        FROM play_event JOIN song_artist ON song_artist.file_path = play_event.file_path
+        WHERE play_event.started_at BETWEEN COALESCE(?1, 0) AND COALESCE(?2, 9223372036854775807)
+            AND song_artist.role IN ('main', 'featured')
+        GROUP BY song_artist.artist
+        ORDER BY play_count DESC, listened_ms DESC LIMIT COALESCE(?3, 10)",
+        params![from, to, limit],
+        app
+    )
+}
+
+#[tauri::command]
+pub fn get_top_albums(from: Option<i64>, to: Option<i64>, limit: Option<i64>, app: tauri::AppHandle) -> Result<String, String> {
+    query_row_params(
+        "SELECT
+            album.*,
+            SUM(play_event.completed) AS play_count,
+            SUM(play_event.listened_ms) AS listened_ms
+        FROM play_event
+        JOIN song ON song.file_path = play_event.file_path
+        JOIN album ON album.id = song.album_id
+        WHERE play_event.started_at BETWEEN COALESCE(?1, 0) AND COALESCE(?2, 9223372036854775807)
+        GROUP BY album.id
+        ORDER BY play_count DESC, listened_ms DESC LIMIT COALESCE(?3, 10)",
+        params![from, to, limit],
+        app
+    )
+}
+
+#[tauri::command]
+pub fn get_top_tracks(
+    from: Option<i64>,
+    to: Option<i64>,
+    artist: Option<String>,
+    limit: Option<i64>,
+    app: tauri::AppHandle
+) -> Result<String, String> {
+    query_row_params(
+        "SELECT
+            song_with_stats.*,
+            SUM(play_event.completed) AS period_play_count,
+            SUM(play_event.listened_ms) AS listened_ms
+        FROM play_event JOIN song_with_stats ON song_with_stats.file_path = play_event.file_path
+        WHERE play_event.started_at BETWEEN COALESCE(?1, 0) AND COALESCE(?2, 9223372036854775807)
+        AND (?3 IS NULL OR song_with_stats.artist = ?3 OR song_with_stats.album_artist = ?3)
+        GROUP BY song_with_stats.file_path
+        HAVING period_play_count > 0
+        ORDER BY period_play_count DESC, listened_ms DESC LIMIT COALESCE(?4, 10)",
+        params![from, to, artist, limit],
+        app
+    )
+}
+
+#[tauri::command]
+pub fn get_top_genres(from: Option<i64>, to: Option<i64>, limit: Option<i64>, app: tauri::AppHandle) -> Result<String, String> {
+    query_row_params(
+        "SELECT
+            song.genre AS name,
+            SUM(play_event.completed) AS play_count,
+            SUM(play_event.listened_ms) AS listened_ms
+        FROM play_event JOIN song ON song.file_path = play_event.file_path
+        WHERE play_event.started_at BETWEEN COALESCE(?1, 0) AND COALESCE(?2, 9223372036854775807)
+        AND song.genre IS NOT NULL AND song.genre != ''
+        GROUP BY song.genre
+        ORDER BY play_count DESC, listened_ms DESC LIMIT COALESCE(?3, 10)",
+        params![from, to, limit],
+        app
+    )
+}
+
+#[tauri::command]
+pub fn get_listening_time(from: Option<i64>, to: Option<i64>, period: String, app: tauri::AppHandle) -> Result<String, String> {
+    let bucket = match period.as_str() {
+        "day" => "date(started_at, 'unixepoch', 'localtime')",
+        "week" => "date(started_at, 'unixepoch', 'localtime', 'weekday 0', '-6 days')",
+        _ => return Err(format!("Unknown period: {}", period)),
+    };
+
+    query_row_params(
+        &format!(
+            "SELECT
+                {} AS period,
+                SUM(listened_ms) AS listened_ms,
+                SUM(completed) AS play_count
+            FROM play_event
+            WHERE started_at BETWEEN COALESCE(?1, 0) AND COALESCE(?2, 9223372036854775807)
+            GROUP BY period ORDER BY period",
+            bucket
+        ),
+        params![from, to],
+        app
+    )
+}
+
+#[tauri::command]
+pub fn get_listening_streaks(limit: Option<i64>, app: tauri::AppHandle) -> Result<String, String> {
+    query_row_params(
+        "WITH days AS (
+            SELECT DISTINCT date(started_at, 'unixepoch', 'localtime') AS day
+            FROM play_event WHERE completed = 1
+        ),
+        islands AS (
+            SELECT day, julianday(day) - ROW_NUMBER() OVER (ORDER BY day) AS island FROM days
+        )
+        SELECT
+            MIN(day) AS start_date,
+            MAX(day) AS end_date,
+            COUNT(*) AS days,
+            MAX(day) >= date('now', 'localtime', '-1 day') AS is_current
+        FROM islands GROUP BY island
+        ORDER BY days DESC, end_date DESC LIMIT COALESCE(?1, 10)",
+        params![limit],
+        app
+    )
+}
+
+#[tauri::command]
+pub fn get_songs_by_quality(
+    codec: Option<String>,
+    lossless: Option<bool>,
+    min_sample_rate: Option<i64>,
+    min_bit_depth: Option<i64>,
+    max_bitrate: Option<i64>,
+    app: tauri::AppHandle
+) -> Result<String, String> {
+    query_row_params(
+        "SELECT * FROM song_with_stats
+        WHERE (?1 IS NULL OR codec = ?1 COLLATE NOCASE)
+        AND (?2 IS NULL OR lossless = ?2)
+        AND (?3 IS NULL OR sample_rate >= ?3)
+        AND (?4 IS NULL OR bit_depth >= ?4)
+        AND (?5 IS NULL OR bitrate <= ?5)
+        ORDER BY album_artist, album_title, disc_number, track_number",
+        params![codec, lossless, min_sample_rate, min_bit_depth, max_bitrate],
+        app
+    )
+}
+
+#[tauri::command]
+pub fn get_forgotten_favourites(
+    min_plays: Option<i64>,
+    days: Option<i64>,
+    limit: Option<i64>,
+    app: tauri::AppHandle
+) -> Result<String, String> {
+    query_row_params(
+        "SELECT * FROM song_with_stats
+        WHERE play_count >= COALESCE(?1, 5)
+        AND last_played < strftime('%s', 'now') - COALESCE(?2, 90) * 86400
+        ORDER BY play_count DESC, last_played LIMIT COALESCE(?3, 25)",
+        params![min_plays, days, limit],
+        app
+    )
+}
+
+#[tauri::command]
+pub fn remove_album(album: String, artist: String, id: Option<i64>, app: tauri::AppHandle) -> Result<String, String> {
+    let conn = get_db_connection(app).map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "DELETE FROM song WHERE album_id = ?3 OR (?3 IS NULL AND album_title = ?1 AND album_artist = ?2)",
+        params![album, artist, id],
     ).map_err(|e| e.to_string())?;
 
     conn.execute(
-        "DELETE FROM album WHERE title = ?1 AND artist = ?2",
-        params![album, artist],
+        "DELETE FROM album WHERE id = ?3 OR (?3 IS NULL AND title = ?1 AND artist = ?2)",
+        params![album, artist, id],
     ).map_err(|e| e.to_string())?;
 
     Ok("Album removed".into())
@@ -435,48 +1005,372 @@ pub fn remove_song(file_path: String, app: tauri::AppHandle) -> Result<String, S
 }
 
 #[tauri::command]
-pub fn update_metadata_song(
+pub fn set_song_rating(
+    file_paths: Vec<String>,
+    rating: f64,
+    write_to_file: bool,
+    app: tauri::AppHandle
+) -> Result<String, String> {
+    if !(0.0..=5.0).contains(&rating) || (rating * 2.0).fract() != 0.0 {
+        return Err(format!("Invalid rating: {}", rating));
+    }
+    let half_stars = (rating * 2.0) as u8;
+
+    let mut conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    for file_path in &file_paths {
+        tx.execute(
+            "INSERT INTO song_rating (file_path, rating) VALUES (?1, ?2)
+            ON CONFLICT (file_path) DO UPDATE SET rating = excluded.rating",
+            params![file_path, half_stars],
+        ).map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+
+    let mut message = format!("Rated {} songs", file_paths.len());
+    if write_to_file {
+        let failed = file_paths
+            .iter()
+            .filter(|file_path| {
+                tags::write_rating(Path::new(file_path), half_stars)
+                    .map_err(|e| println!("Failed to write rating to {}: {}", file_path, e))
+                    .is_err()
+            })
+            .count();
+
+        if failed > 0 {
+            message += format!(", {} could not be written to file", failed).as_str();
+        }
+    }
+
+    Ok(message)
+}
+
+#[tauri::command]
+pub fn set_song_loved(file_paths: Vec<String>, loved: bool, app: tauri::AppHandle) -> Result<String, String> {
+    let mut conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    for file_path in &file_paths {
+        tx.execute(
+            "INSERT INTO song_rating (file_path, loved) VALUES (?1, ?2)
+            ON CONFLICT (file_path) DO UPDATE SET loved = excluded.loved",
+            params![file_path, loved],
+        ).map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(if loved { "Added to loved songs" } else { "Removed from loved songs" }.into())
+}
+
+#[tauri::command]
+pub fn get_loved_songs(app: tauri::AppHandle) -> Result<String, String> {
+    query_row("SELECT * FROM song_with_stats WHERE loved = 1 ORDER BY artist, album_title, disc_number, track_number", app)
+}
+
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct EditResult {
+    pub file_path: String,
+    pub error: Option<String>,
+    pub warning: Option<String>,
+}
+
+fn in_parallel<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = thread::available_parallelism().map_or(4, |n| n.get());
+    let chunk_size = items.len().div_ceil(threads).max(1);
+
+    thread::scope(|scope| {
+        let workers = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(&f).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect()
+    })
+}
+
+#[tauri::command]
+pub async fn batch_edit_songs(
+    file_paths: Vec<String>,
+    patch: tags::TagPatch,
     location_on_disk: String,
-    file_path: String,
-    cover_path: String,
-    title: String,
-    artist: String,
-    album_title: String,
-    album_artist: String,
-    track_number: u16,
-    disc_number: u16,
-    year: i32,
-    genre: String,
+    cover_path: Option<String>,
+    embed_cover: Option<bool>,
+    copy_cover: Option<bool>,
     app: tauri::AppHandle
 ) -> Result<String, String> {
-    let mut tag = Tag::new()
-        .read_from_path(&file_path)
-        .map_err(|e| e.to_string())?;
-    let conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    let results = tauri::async_runtime::spawn_blocking(move || {
+        let cover_path = match cover_path.filter(|path| !path.is_empty()) {
+            Some(path) if copy_cover.unwrap_or(false) => {
+                Some(covers::copy_cover_to_folder(Path::new(&path), Path::new(&location_on_disk)).map_err(|e| e.to_string())?)
+            }
+            path => path,
+        };
+        let cover_data = match &cover_path {
+            Some(path) if embed_cover.unwrap_or(false) => Some(covers::prepare_embedded_cover(Path::new(path)).map_err(|e| e.to_string())?),
+            _ => None,
+        };
+
+        edit_songs(&file_paths, &patch, cover_path.as_deref(), cover_data.as_deref(), &app)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    serde_json::to_string(&results).map_err(|e| e.to_string())
+}
 
-    tag.set_title(&title);
-    tag.set_artist(&artist);
-    tag.set_album_title(&album_title);
-    tag.set_album_artist(&album_artist);
-    tag.set_track_number(track_number);
-    tag.set_disc_number(disc_number);
-    tag.set_year(year);
-    tag.set_genre(&genre);
+fn edit_songs(
+    file_paths: &[String],
+    patch: &tags::TagPatch,
+    cover_path: Option<&str>,
+    cover_data: Option<&[u8]>,
+    app: &tauri::AppHandle
+) -> Result<Vec<EditResult>, String> {
+    let prepared = in_parallel(file_paths, |file_path| {
+        tags::prepare_patch(Path::new(file_path), patch, cover_data).map_err(|e| e.to_string())
+    });
 
-    tag.write_to_path(&file_path).map_err(|e| e.to_string())?;
+    let mut conn = get_db_connection(app.clone()).map_err(|e| e.to_string())?;
+    let mut edited = Vec::new();
+    for (file_path, prepared) in file_paths.iter().zip(&prepared) {
+        let Ok((_, changes)) = prepared else { continue };
+        let previous_cover_path = match cover_path {
+            Some(_) => conn
+                .query_row("SELECT cover_path FROM song WHERE file_path = ?1", params![file_path], |row| row.get(0))
+                .optional()
+                .map_err(|e| e.to_string())?
+                .flatten(),
+            None => None,
+        };
+        edited.push(edit_history::EditedFile {
+            file_path: file_path.clone(),
+            changes: changes.clone(),
+            previous_cover_path,
+            cover_path: cover_path.map(String::from),
+        });
+    }
+    let edit_id = edit_history::record_edit(&conn, None, &edited).map_err(|e| e.to_string())?;
+
+    let done = AtomicUsize::new(0);
+    let items = file_paths.iter().zip(prepared).collect::<Vec<_>>();
+    let mut errors = in_parallel(&items, |(file_path, prepared)| {
+        let saved = match prepared {
+            Ok((tag, _)) => tags::save_native_tag(Path::new(file_path), tag).map_err(|e| e.to_string()),
+            Err(e) => Err(e.clone()),
+        };
+        let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+        let _ = app.emit("edit_progress", crate::Payload { message: done.to_string() });
+        saved.err()
+    });
 
-    // TOOD: Add option to copy cover art to song directory
-    conn.execute(
-        "REPLACE INTO album (location_on_disk, cover_path, title, artist)
-        VALUES (?1, ?2, ?3, ?4)",
-        params![location_on_disk, cover_path, album_title, album_artist],
-    ).map_err(|e| e.to_string())?;
+    // Files that couldn't be written weren't changed, there is nothing to undo for them
+    if let Some(edit_id) = edit_id {
+        for (file_path, _) in file_paths.iter().zip(&errors).filter(|(_, error)| error.is_some()) {
+            if let Err(e) = edit_history::forget_file(&conn, edit_id, file_path) {
+                println!("Failed to remove {} from edit {}: {}", file_path, edit_id, e);
+            }
+        }
+    }
 
-    conn.execute(
-        "UPDATE song SET cover_path = ?2, title = ?3, artist = ?4, album_title = ?5, album_artist = ?6, track_number = ?7, disc_number = ?8, year = ?9, genre = ?10
-        WHERE file_path = ?1",
-        params![file_path, cover_path, title, artist, album_title, album_artist, track_number, disc_number, year, genre]
-    ).map_err(|e| e.to_string())?;
+    // The files are written at this point, so the library failing to update is reported per file
+    let edited = file_paths.iter().zip(&errors).filter(|(_, error)| error.is_none()).map(|(file_path, _)| file_path.clone()).collect::<Vec<_>>();
+    let updated = conn.transaction().and_then(|tx| {
+        let (refresh_errors, albums) = refresh_songs(&tx, &edited);
+        for (file_path, error) in file_paths.iter().zip(errors.iter_mut()).filter(|(_, error)| error.is_none()) {
+            *error = refresh_errors.get(file_path).cloned();
+            if let (None, Some(cover_path)) = (&error, cover_path) {
+                tx.execute("UPDATE song SET cover_path = ?2 WHERE file_path = ?1", params![file_path, cover_path])?;
+                tx.execute(
+                    "UPDATE album SET cover_path = ?2 WHERE id = (SELECT album_id FROM song WHERE file_path = ?1)",
+                    params![file_path, cover_path]
+                )?;
+            }
+        }
+        sync_albums(&tx, &albums)?;
+        tx.commit()
+    });
+    if let Err(e) = updated {
+        for error in errors.iter_mut().filter(|error| error.is_none()) {
+            *error = Some(format!("The tags were saved, but the library couldn't be updated: {}", e));
+        }
+    }
+
+    Ok(file_paths
+        .iter()
+        .zip(errors)
+        .map(|(file_path, error)| EditResult { file_path: file_path.clone(), error, warning: None })
+        .collect())
+}
+
+fn in_savepoint<T>(conn: &Connection, update: impl FnOnce() -> Result<T, rusqlite::Error>) -> Result<T, rusqlite::Error> {
+    conn.execute_batch("SAVEPOINT refresh_song")?;
+    let result = update();
+    match result {
+        Ok(_) => conn.execute_batch("RELEASE refresh_song")?,
+        Err(_) => conn.execute_batch("ROLLBACK TO refresh_song; RELEASE refresh_song")?,
+    }
+    result
+}
+
+pub(crate) fn refresh_songs(conn: &Connection, file_paths: &[String]) -> (HashMap<String, String>, HashSet<i64>) {
+    let splitter = ArtistSplitter::load(conn);
+    let mut errors = HashMap::new();
+    let mut albums = HashSet::new();
+    let mut moved: HashMap<(Option<i64>, AlbumKey), AlbumMetadata> = HashMap::new();
+
+    for file_path in file_paths {
+        let read = get_song_metadata(Path::new(file_path)).and_then(|song| {
+            let current = conn
+                .query_row(
+                    "SELECT album.id, album.title, album.artist, album.compilation, COALESCE(song.cover_path, '') FROM song
+                    JOIN album ON album.id = song.album_id WHERE song.file_path = ?1",
+                    params![file_path],
+                    |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, bool>(3)?, row.get::<_, String>(4)?))
+                )
+                .optional()?;
+            Ok((song, current))
+        });
+        let (mut song, current) = match read {
+            Ok(read) => read,
+            Err(e) => {
+                errors.insert(file_path.clone(), e.to_string());
+                continue;
+            }
+        };
+
+        match current {
+            Some((id, title, artist, _, cover_path))
+                if title == song.album_title && (!song.album_artist_tagged || artist == song.album_artist) =>
+            {
+                // Untagged album artists were resolved for the whole album when it was scanned
+                song.album_artist = artist;
+                albums.insert(id);
+                if let Err(e) = in_savepoint(conn, || insert_song(conn, &song, &cover_path, id, &splitter)) {
+                    errors.insert(file_path.clone(), e.to_string());
+                }
+            }
+            current => {
+                let previous_album = current.as_ref().map(|(id, ..)| *id);
+                // Songs of a compilation without an album artist tag keep being credited to Various Artists
+                let was_compilation = current.as_ref().is_some_and(|(_, _, _, compilation, _)| *compilation);
+                albums.extend(previous_album);
+
+                let album = moved.entry((previous_album, AlbumKey::new(&song))).or_insert_with(|| AlbumMetadata {
+                    location_on_disk: song.album_dir.clone(),
+                    cover_path: current.map(|(.., cover_path)| cover_path),
+                    artist_image_path: None,
+                    title: song.album_title.clone(),
+                    artist: song.album_artist.clone(),
+                    year: song.year,
+                    genre: song.genre.clone(),
+                    release_mbid: song.musicbrainz_ids.release.clone(),
+                    compilation: false,
+                    songs: Vec::new(),
+                });
+                album.compilation |= song.compilation || (was_compilation && !song.album_artist_tagged);
+                album.songs.push(song);
+            }
+        }
+    }
+
+    for mut album in moved.into_values() {
+        resolve_album_artist(&mut album, &splitter);
+        let cover_path = album.cover_path.clone().unwrap_or_default();
+
+        let album_id = conn.query_row(
+            "INSERT INTO album (location_on_disk, cover_path, title, artist, year, genre, release_mbid, compilation)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            ON CONFLICT (artist, title, location_on_disk) DO UPDATE SET title = excluded.title
+            RETURNING id",
+            params![
+                &album.location_on_disk,
+                &cover_path,
+                &album.title,
+                &album.artist,
+                &album.year,
+                &album.genre,
+                &album.release_mbid,
+                &album.compilation
+            ],
+            |row| row.get::<_, i64>(0)
+        );
+        let album_id = match album_id {
+            Ok(id) => id,
+            Err(e) => {
+                for song in album.songs {
+                    errors.insert(song.file_path, e.to_string());
+                }
+                continue;
+            }
+        };
+
+        albums.insert(album_id);
+        for song in &album.songs {
+            if let Err(e) = in_savepoint(conn, || insert_song(conn, song, &cover_path, album_id, &splitter)) {
+                errors.insert(song.file_path.clone(), e.to_string());
+            }
+        }
+    }
+
+    (errors, albums)
+}
+
+pub(crate) fn sync_albums(conn: &Connection, album_ids: &HashSet<i64>) -> Result<(), rusqlite::Error> {
+    for id in album_ids {
+        let removed = conn.execute(
+            "DELETE FROM album WHERE id = ?1 AND NOT EXISTS (SELECT 1 FROM song WHERE song.album_id = ?1)",
+            params![id]
+        )?;
+        if removed > 0 {
+            continue;
+        }
+
+        conn.execute(
+            "UPDATE album SET
+                year = COALESCE((SELECT year FROM song WHERE album_id = ?1 AND year > 0 GROUP BY year ORDER BY COUNT(*) DESC, year LIMIT 1), 0),
+                genre = COALESCE((SELECT genre FROM song WHERE album_id = ?1 AND genre <> '' GROUP BY genre ORDER BY COUNT(*) DESC, genre LIMIT 1), ''),
+                release_mbid = (SELECT release_mbid FROM song WHERE album_id = ?1 AND release_mbid IS NOT NULL GROUP BY release_mbid ORDER BY COUNT(*) DESC LIMIT 1)
+            WHERE id = ?1",
+            params![id]
+        )?;
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_tag_fields(file_path: String) -> Result<String, String> {
+    let tag = tags::read_native_tag(Path::new(&file_path)).map_err(|e| e.to_string())?;
+
+    Ok(serde_json::json!({
+        "format": tag.format_name(),
+        "fields": tag.fields(),
+    })
+    .to_string())
+}
+
+#[tauri::command]
+pub fn set_tag_fields(file_path: String, fields: Vec<tags::TagField>, app: tauri::AppHandle) -> Result<String, String> {
+    let path = Path::new(&file_path);
+    let patch = tags::TagPatch { fields, ..Default::default() };
+    let (tag, changes) = tags::prepare_patch(path, &patch, None).map_err(|e| e.to_string())?;
+
+    let mut conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    let edited = edit_history::EditedFile { file_path: file_path.clone(), changes, previous_cover_path: None, cover_path: None };
+    let edit_id = edit_history::record_edit(&conn, None, &[edited]).map_err(|e| e.to_string())?;
+    if let Err(e) = tags::save_native_tag(path, &tag) {
+        if let Some(edit_id) = edit_id {
+            edit_history::forget_file(&conn, edit_id, &file_path).map_err(|e| e.to_string())?;
+        }
+        return Err(e.to_string());
+    }
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let (mut errors, albums) = refresh_songs(&tx, std::slice::from_ref(&file_path));
+    if let Some(error) = errors.remove(&file_path) {
+        return Err(error);
+    }
+    sync_albums(&tx, &albums).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
 
     Ok("Song updated".into())
 }
@@ -0,0 +1,235 @@
+use std::{error::Error, path::Path};
+
+use rusqlite::{params, types::Type, Connection, OptionalExtension};
+
+use crate::db::{get_db_connection, query_row_params, refresh_songs, sync_albums, EditResult};
+use crate::tags::{self, NativeTag, TagChanges, TagField, TagPatch};
+
+pub(crate) struct EditedFile {
+    pub file_path: String,
+    pub changes: TagChanges,
+    pub previous_cover_path: Option<String>,
+    pub cover_path: Option<String>,
+}
+
+impl EditedFile {
+    fn is_unchanged(&self) -> bool {
+        self.changes.is_empty() && self.cover_path == self.previous_cover_path
+    }
+}
+
+pub(crate) fn record_edit(conn: &Connection, undo_of: Option<i64>, files: &[EditedFile]) -> Result<Option<i64>, Box<dyn Error>> {
+    let changed = files.iter().filter(|file| !file.is_unchanged()).collect::<Vec<_>>();
+    if changed.is_empty() {
+        return Ok(None);
+    }
+
+    conn.execute(
+        "INSERT INTO edit_history (edited_at, undo_of) VALUES (strftime('%s', 'now'), ?1)",
+        params![undo_of],
+    )?;
+    let id = conn.last_insert_rowid();
+
+    let mut insert = conn.prepare(
+        "INSERT INTO edit_history_file (edit_id, file_path, previous_fields, new_fields, pictures_changed, previous_cover_path, cover_path)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )?;
+    for file in changed {
+        insert.execute(params![
+            id,
+            file.file_path,
+            serde_json::to_string(&file.changes.previous)?,
+            serde_json::to_string(&file.changes.new)?,
+            file.changes.pictures,
+            file.previous_cover_path,
+            file.cover_path
+        ])?;
+    }
+
+    Ok(Some(id))
+}
+
+pub(crate) fn forget_file(conn: &Connection, edit_id: i64, file_path: &str) -> Result<(), rusqlite::Error> {
+    conn.execute("DELETE FROM edit_history_file WHERE edit_id = ?1 AND file_path = ?2", params![edit_id, file_path])?;
+    conn.execute(
+        "DELETE FROM edit_history WHERE id = ?1 AND NOT EXISTS (SELECT 1 FROM edit_history_file WHERE edit_id = ?1)",
+        params![edit_id],
+    )?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_edit_history(limit: Option<i64>, app: tauri::AppHandle) -> Result<String, String> {
+    query_row_params(
+        "SELECT
+            edit_history.id,
+            edit_history.edited_at,
+            edit_history.undo_of,
+            edit_history.undone_at,
+            COUNT(*) AS song_count,
+            MAX(edit_history_file.pictures_changed) AS pictures_changed,
+            MIN(song.title) AS title,
+            MIN(song.album_title) AS album_title
+        FROM edit_history
+        JOIN edit_history_file ON edit_history_file.edit_id = edit_history.id
+        LEFT JOIN song ON song.file_path = edit_history_file.file_path
+        GROUP BY edit_history.id
+        ORDER BY edit_history.id DESC
+        LIMIT COALESCE(?1, 50)",
+        params![limit],
+        app,
+    )
+}
+
+struct HistoryFile {
+    file_path: String,
+    previous_fields: Vec<TagField>,
+    new_fields: Vec<TagField>,
+    pictures_changed: bool,
+    previous_cover_path: Option<String>,
+    cover_path: Option<String>,
+}
+
+fn changed_since(tag: &NativeTag, new_fields: &[TagField]) -> Vec<String> {
+    let current = tag.fields();
+    new_fields
+        .iter()
+        .filter(|field| {
+            let values = current.iter().find(|current| current.key == field.key).map(|current| current.values.as_slice());
+            values.unwrap_or_default() != field.values.as_slice()
+        })
+        .map(|field| field.key.clone())
+        .collect()
+}
+
+#[tauri::command]
+pub async fn undo_edit(id: i64, app: tauri::AppHandle) -> Result<String, String> {
+    let results = tauri::async_runtime::spawn_blocking(move || undo(id, &app)).await.map_err(|e| e.to_string())??;
+    serde_json::to_string(&results).map_err(|e| e.to_string())
+}
+
+fn undo(id: i64, app: &tauri::AppHandle) -> Result<Vec<EditResult>, String> {
+    let mut conn = get_db_connection(app.clone()).map_err(|e| e.to_string())?;
+    let undone_at: Option<Option<i64>> = conn
+        .query_row("SELECT undone_at FROM edit_history WHERE id = ?1", params![id], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())?;
+    match undone_at {
+        None => return Err(format!("Edit {} doesn't exist", id)),
+        Some(Some(_)) => return Err("This edit was already undone".into()),
+        Some(None) => {}
+    }
+
+    let files = conn
+        .prepare(
+            "SELECT file_path, previous_fields, new_fields, pictures_changed, previous_cover_path, cover_path
+            FROM edit_history_file WHERE edit_id = ?1",
+        )
+        .and_then(|mut statement| {
+            statement
+                .query_map(params![id], |row| {
+                    let fields = |index: usize| -> rusqlite::Result<Vec<TagField>> {
+                        serde_json::from_str(&row.get::<_, String>(index)?)
+                            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(e)))
+                    };
+                    Ok(HistoryFile {
+                        file_path: row.get(0)?,
+                        previous_fields: fields(1)?,
+                        new_fields: fields(2)?,
+                        pictures_changed: row.get(3)?,
+                        previous_cover_path: row.get(4)?,
+                        cover_path: row.get(5)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut results = Vec::with_capacity(files.len());
+    let mut restored = Vec::new();
+    for file in files {
+        let prepared = tags::read_native_tag(Path::new(&file.file_path)).and_then(|tag| {
+            let changed = changed_since(&tag, &file.new_fields);
+            if !changed.is_empty() {
+                return Err(format!("Changed since this edit: {}", changed.join(", ")).into());
+            }
+            let patch = TagPatch { fields: file.previous_fields.clone(), ..Default::default() };
+            tags::prepare_patch(Path::new(&file.file_path), &patch, None)
+        });
+        let warning = file.pictures_changed.then(|| "The pictures this edit changed can't be restored".to_string());
+
+        match prepared {
+            Ok((tag, changes)) => {
+                results.push(EditResult { file_path: file.file_path.clone(), error: None, warning });
+                restored.push((tag, file, changes));
+            }
+            Err(e) => results.push(EditResult { file_path: file.file_path, error: Some(e.to_string()), warning: None }),
+        }
+    }
+
+    // Like any edit, the undo is recorded before files are written
+    let edited = restored
+        .iter()
+        .map(|(_, file, changes)| EditedFile {
+            file_path: file.file_path.clone(),
+            changes: changes.clone(),
+            previous_cover_path: file.cover_path.clone(),
+            cover_path: file.previous_cover_path.clone(),
+        })
+        .collect::<Vec<_>>();
+    let undo_id = record_edit(&conn, Some(id), &edited).map_err(|e| e.to_string())?;
+
+    let mut written = Vec::new();
+    for (tag, file, _) in restored {
+        let result = results.iter_mut().find(|result| result.file_path == file.file_path).unwrap();
+        match tags::save_native_tag(Path::new(&file.file_path), &tag) {
+            Ok(()) => written.push(file),
+            Err(e) => {
+                result.error = Some(e.to_string());
+                result.warning = None;
+                if let Some(undo_id) = undo_id {
+                    forget_file(&conn, undo_id, &file.file_path).map_err(|e| e.to_string())?;
+                }
+            }
+        }
+    }
+    // Nothing was restored, the edit can still be undone later
+    if written.is_empty() {
+        return Ok(results);
+    }
+    conn.execute("UPDATE edit_history SET undone_at = strftime('%s', 'now') WHERE id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
+
+    let file_paths = written.iter().map(|file| file.file_path.clone()).collect::<Vec<_>>();
+    let updated = conn.transaction().and_then(|tx| {
+        let (errors, albums) = refresh_songs(&tx, &file_paths);
+        for result in results.iter_mut() {
+            if let Some(error) = errors.get(&result.file_path) {
+                result.error = Some(error.clone());
+            }
+        }
+
+        // Covers that were replaced again since the edit are left alone
+        for file in written.iter().filter(|file| file.cover_path.is_some()) {
+            tx.execute(
+                "UPDATE album SET cover_path = ?3
+                WHERE id = (SELECT album_id FROM song WHERE file_path = ?1) AND cover_path IS ?2",
+                params![file.file_path, file.cover_path, file.previous_cover_path],
+            )?;
+            tx.execute(
+                "UPDATE song SET cover_path = ?3 WHERE file_path = ?1 AND cover_path IS ?2",
+                params![file.file_path, file.cover_path, file.previous_cover_path],
+            )?;
+        }
+
+        sync_albums(&tx, &albums)?;
+        tx.commit()
+    });
+    if let Err(e) = updated {
+        for result in results.iter_mut().filter(|result| result.error.is_none()) {
+            result.error = Some(format!("The tags were restored, but the library couldn't be updated: {}", e));
+        }
+    }
+
+    Ok(results)
+}
@@ -0,0 +1,326 @@
+use std::{collections::HashMap, error::Error, fs, path::Path};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use rusqlite::params;
+use serde_json::{json, Value};
+
+use crate::db::get_db_connection;
+use crate::matching::{LibraryIndex, TrackQuery};
+
+const DUPLICATE_WINDOW_SECS: i64 = 60;
+
+#[derive(Debug, Default)]
+struct HistoryEntry {
+    artist: String,
+    title: String,
+    album: Option<String>,
+    duration: Option<u64>,
+    timestamp: i64,
+}
+
+const NAIVE_DATE_TIME_FORMATS: [&str; 7] = [
+    "%d %b %Y %H:%M",
+    "%d %b %Y, %H:%M",
+    "%d %b %Y %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+const NAIVE_DATE_FORMATS: [&str; 2] = ["%Y-%m-%d", "%d %b %Y"];
+
+fn non_empty(value: Option<&str>) -> Option<String> {
+    value.map(str::trim).filter(|v| !v.is_empty()).map(String::from)
+}
+
+fn parse_zoned_timestamp(value: &str) -> Option<i64> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.timestamp());
+    }
+
+    ["%Y-%m-%dT%H:%M:%S%.f%#z", "%Y-%m-%d %H:%M:%S%.f%#z"]
+        .iter()
+        .find_map(|format| DateTime::parse_from_str(value, format).ok())
+        .map(|time| time.timestamp())
+}
+
+fn parse_timestamp(value: &str) -> Option<i64> {
+    let value = value.trim();
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()) {
+        let timestamp = value.parse::<i64>().ok()?;
+        return Some(if timestamp > 100_000_000_000 { timestamp / 1000 } else { timestamp });
+    }
+    if let Some(timestamp) = parse_zoned_timestamp(value) {
+        return Some(timestamp);
+    }
+
+    if let Some(time) = NAIVE_DATE_TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+    {
+        return Some(time.and_utc().timestamp());
+    }
+
+    NAIVE_DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|time| time.and_utc().timestamp())
+}
+
+fn parse_lastfm_csv(content: &str) -> Result<Vec<HistoryEntry>, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let mut records = reader.records().peekable();
+
+    let mut columns = HashMap::from([("artist", 0), ("album", 1), ("track", 2), ("date", 3)]);
+    if let Some(Ok(first)) = records.peek() {
+        let header = first.iter().map(|h| h.trim().to_lowercase()).collect::<Vec<_>>();
+        let find = |names: &[&str]| header.iter().position(|h| names.contains(&h.as_str()));
+
+        if let (Some(artist), Some(track)) = (find(&["artist", "artist_name"]), find(&["track", "title", "name", "track_name"])) {
+            columns.insert("artist", artist);
+            columns.insert("track", track);
+            columns.insert("album", find(&["album", "album_name", "release_name"]).unwrap_or(usize::MAX));
+            columns.insert("date", find(&["uts", "timestamp", "date", "utc_time", "played_at"]).unwrap_or(usize::MAX));
+            records.next();
+        }
+    }
+
+    let mut entries = Vec::new();
+    for record in records {
+        let record = record?;
+        let field = |name: &str| non_empty(record.get(columns[name]));
+        let (Some(artist), Some(title), Some(timestamp)) = (
+            field("artist"),
+            field("track"),
+            field("date").and_then(|d| parse_timestamp(&d)),
+        ) else {
+            continue;
+        };
+
+        entries.push(HistoryEntry { artist, title, album: field("album"), duration: None, timestamp });
+    }
+
+    Ok(entries)
+}
+
+fn lastfm_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => non_empty(Some(text)),
+        Value::Object(_) => non_empty(value["#text"].as_str()).or(non_empty(value["name"].as_str())),
+        _ => None,
+    }
+}
+
+fn collect_lastfm_tracks(value: &Value, entries: &mut Vec<HistoryEntry>) {
+    match value {
+        Value::Array(items) => items.iter().for_each(|item| collect_lastfm_tracks(item, entries)),
+        Value::Object(object) if object.contains_key("artist") && object.contains_key("name") => {
+            let timestamp = match &value["date"] {
+                Value::Object(date) => date.get("uts").and_then(lastfm_text),
+                other => lastfm_text(other),
+            };
+            let (Some(artist), Some(title), Some(timestamp)) = (
+                lastfm_text(&value["artist"]),
+                lastfm_text(&value["name"]),
+                timestamp.and_then(|t| parse_timestamp(&t)),
+            ) else {
+                return;
+            };
+
+            entries.push(HistoryEntry {
+                artist,
+                title,
+                album: lastfm_text(&value["album"]),
+                duration: None,
+                timestamp,
+            });
+        }
+        Value::Object(object) => object.values().for_each(|value| collect_lastfm_tracks(value, entries)),
+        _ => {}
+    }
+}
+
+fn listenbrainz_entry(listen: &Value) -> Option<HistoryEntry> {
+    let metadata = &listen["track_metadata"];
+    let info = &metadata["additional_info"];
+    let duration = info["duration_ms"]
+        .as_u64()
+        .or(info["duration"].as_u64().map(|secs| secs * 1000));
+
+    Some(HistoryEntry {
+        artist: non_empty(metadata["artist_name"].as_str())?,
+        title: non_empty(metadata["track_name"].as_str())?,
+        album: non_empty(metadata["release_name"].as_str()),
+        duration,
+        timestamp: listen["listened_at"].as_i64()?,
+    })
+}
+
+fn parse_json_history(content: &str) -> Result<Vec<HistoryEntry>, Box<dyn Error>> {
+    let values = match serde_json::from_str::<Value>(content) {
+        Ok(value) => vec![value],
+        Err(e) => {
+            let lines = content
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(serde_json::from_str::<Value>)
+                .collect::<Result<Vec<_>, _>>();
+            lines.map_err(|_| e)?
+        }
+    };
+
+    let mut entries = Vec::new();
+    for value in &values {
+        let listens = match value {
+            Value::Array(items) => items.iter().collect::<Vec<_>>(),
+            Value::Object(object) if object.contains_key("payload") => {
+                value["payload"]["listens"].as_array().into_iter().flatten().collect()
+            }
+            other => vec![other],
+        };
+
+        if listens.iter().any(|listen| listen.get("track_metadata").is_some()) {
+            entries.extend(listens.into_iter().filter_map(listenbrainz_entry));
+        } else {
+            collect_lastfm_tracks(value, &mut entries);
+        }
+    }
+
+    Ok(entries)
+}
+
+fn read_history(path: &Path) -> Result<Vec<HistoryEntry>, Box<dyn Error>> {
+    let content = fs::read_to_string(path)?;
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "csv" => parse_lastfm_csv(&content),
+        "json" | "jsonl" => parse_json_history(&content),
+        _ => Err("Unsupported history format, expected a .csv or .json export".into()),
+    }
+}
+
+#[tauri::command]
+pub fn import_listening_history(path: &Path, app: tauri::AppHandle) -> Result<String, String> {
+    let entries = read_history(path).map_err(|e| e.to_string())?;
+
+    let mut conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    let library = LibraryIndex::load(&conn).map_err(|e| e.to_string())?;
+
+    // Histories repeat the same tracks a lot, so each one is only matched once
+    let mut matches: HashMap<(String, String, Option<String>), Option<String>> = HashMap::new();
+    let mut unmatched: HashMap<(String, String, Option<String>), u64> = HashMap::new();
+    let mut imported = 0;
+    let mut duplicates = 0;
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    {
+        let mut insert = tx
+            .prepare(
+                "INSERT INTO play_event (file_path, started_at, listened_ms, completed, skipped)
+                SELECT file_path, ?2, duration, 1, 0 FROM song
+                WHERE file_path = ?1 AND NOT EXISTS (
+                    SELECT 1 FROM play_event
+                    WHERE play_event.file_path = ?1 AND ABS(play_event.started_at - ?2) <= ?3
+                )",
+            )
+            .map_err(|e| e.to_string())?;
+
+        for entry in &entries {
+            let key = (entry.artist.clone(), entry.title.clone(), entry.album.clone());
+            let file_path = matches.entry(key.clone()).or_insert_with(|| {
+                let query = TrackQuery {
+                    artist: Some(entry.artist.clone()),
+                    title: entry.title.clone(),
+                    album: entry.album.clone(),
+                    duration: entry.duration,
+                };
+                library.find_by_tags(&query).map(|m| m.file_path)
+            });
+
+            let Some(file_path) = file_path.as_deref() else {
+                *unmatched.entry(key).or_default() += 1;
+                continue;
+            };
+
+            match insert.execute(params![file_path, entry.timestamp, DUPLICATE_WINDOW_SECS]) {
+                Ok(0) => duplicates += 1,
+                Ok(_) => imported += 1,
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+
+    let mut unmatched = unmatched.into_iter().collect::<Vec<_>>();
+    unmatched.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+
+    let report = json!({
+        "total": entries.len(),
+        "imported": imported,
+        "duplicates": duplicates,
+        "matched_songs": matches.values().filter(|m| m.is_some()).count(),
+        "unmatched_plays": unmatched.iter().map(|(_, count)| count).sum::<u64>(),
+        "unmatched": unmatched
+            .iter()
+            .map(|((artist, title, album), count)| json!({
+                "artist": artist,
+                "title": title,
+                "album": album,
+                "plays": count,
+            }))
+            .collect::<Vec<_>>(),
+    });
+
+    Ok(report.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_timestamp_reads_unix_times() {
+        assert_eq!(parse_timestamp("1580474096"), Some(1_580_474_096));
+        assert_eq!(parse_timestamp("1580474096123"), Some(1_580_474_096));
+    }
+
+    #[test]
+    fn parse_timestamp_reads_lastfm_dates_as_utc() {
+        assert_eq!(parse_timestamp("31 Jan 2020 12:34"), Some(1_580_474_040));
+        assert_eq!(parse_timestamp("31 Jan 2020, 12:34"), Some(1_580_474_040));
+    }
+
+    #[test]
+    fn parse_timestamp_reads_iso_dates_without_zone_as_utc() {
+        assert_eq!(parse_timestamp("2020-01-31T12:34:56"), Some(1_580_474_096));
+        assert_eq!(parse_timestamp("2020-01-31 12:34:56"), Some(1_580_474_096));
+        assert_eq!(parse_timestamp("2020-01-31"), Some(1_580_428_800));
+    }
+
+    #[test]
+    fn parse_timestamp_applies_offsets() {
+        assert_eq!(parse_timestamp("2020-01-31T12:34:56Z"), Some(1_580_474_096));
+        assert_eq!(parse_timestamp("2020-01-31T12:34:56.789Z"), Some(1_580_474_096));
+        assert_eq!(parse_timestamp("2020-01-31T12:34:56+01:00"), Some(1_580_470_496));
+        assert_eq!(parse_timestamp("2020-01-31T12:34:56-05:00"), Some(1_580_492_096));
+        assert_eq!(parse_timestamp("2020-01-31T12:34:56.789+0530"), Some(1_580_454_296));
+        assert_eq!(parse_timestamp("2020-01-31 12:34:56 +01:00"), Some(1_580_470_496));
+    }
+
+    #[test]
+    fn parse_timestamp_rejects_invalid_dates() {
+        assert_eq!(parse_timestamp(""), None);
+        assert_eq!(parse_timestamp("yesterday"), None);
+        assert_eq!(parse_timestamp("2020-13-01T00:00:00"), None);
+        assert_eq!(parse_timestamp("31 Feb 2020 12:34"), None);
+        assert_eq!(parse_timestamp("2020-02-31"), None);
+    }
+}
@@ -1,13 +1,23 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod artists;
 mod audio;
+mod covers;
 mod db;
+mod edit_history;
+mod history;
+mod matching;
+mod playlist;
+mod playlist_file;
+mod scrobble;
+mod settings;
+mod tags;
 
 use rusqlite::Connection;
 use tauri::Manager;
 use tauri_plugin_http::reqwest;
-use std::{fs, path::Path};
+use std::{fs, path::Path, sync::Mutex};
 
 // https://tauri.app/v1/guides/features/events/
 #[derive(Clone, serde::Serialize)]
@@ -17,6 +27,7 @@ pub struct Payload {
 
 struct MusicPlayer {
     sink: rodio::Sink,
+    now_playing: Mutex<Option<audio::NowPlaying>>,
 }
 
 #[tauri::command]
@@ -45,11 +56,16 @@ fn init_audio_player() {
     tauri::Builder::default()
         .setup(|app| {
             let db_path = app.path().app_data_dir().unwrap().join("music.db");
-            let conn = Connection::open(db_path).unwrap();
+            let mut conn = Connection::open(db_path).unwrap();
             let schema_path = app.path().resource_dir().unwrap().join("db/schema.sql");
             let schema = fs::read_to_string(schema_path).unwrap();
 
-            conn.execute_batch(&schema).expect("Failed to create database");
+            db::init_database(&mut conn, &schema).expect("Failed to create database");
+            if let Err(e) = artists::index_missing_credits(&mut conn) {
+                println!("Failed to index artist credits: {}", e);
+            }
+
+            tauri::async_runtime::spawn(scrobble::run(app.handle().clone()));
             Ok(())
         })
         .plugin(
@@ -77,7 +93,9 @@ fn init_audio_player() {
         .plugin(tauri_plugin_http::init())
         .manage(MusicPlayer {
             sink: rodio::Sink::try_new(&stream_handle).unwrap(),
+            now_playing: Mutex::new(None),
         })
+        .manage(scrobble::Scrobbler::new())
         .invoke_handler(tauri::generate_handler![
             download,
             audio::play,
@@ -89,15 +107,58 @@ fn init_audio_player() {
             audio::skip_forward,
             audio::skip_backward,
             audio::set_volume,
+            covers::get_cover_thumbnail,
             db::register_dir,
             db::get_all_albums,
             db::get_albums_by_artist,
             db::get_all_songs,
             db::get_songs_by_album,
+            db::get_songs_by_quality,
             db::get_all_artists,
+            db::set_artist_image,
+            db::clear_artist_image,
             db::remove_album,
             db::remove_song,
-            db::update_metadata_song,
+            db::batch_edit_songs,
+            edit_history::get_edit_history,
+            edit_history::undo_edit,
+            db::get_tag_fields,
+            db::set_tag_fields,
+            db::set_song_rating,
+            db::set_song_loved,
+            db::get_loved_songs,
+            db::get_top_artists,
+            db::get_top_albums,
+            db::get_top_tracks,
+            db::get_top_genres,
+            db::get_listening_time,
+            db::get_listening_streaks,
+            db::get_forgotten_favourites,
+            history::import_listening_history,
+            playlist::create_playlist,
+            playlist::rename_playlist,
+            playlist::delete_playlist,
+            playlist::duplicate_playlist,
+            playlist::get_all_playlists,
+            playlist::get_playlist_songs,
+            playlist::add_to_playlist,
+            playlist::remove_from_playlist,
+            playlist::move_playlist_entry,
+            playlist_file::import_playlist,
+            playlist_file::export_playlist,
+            playlist_file::export_queue,
+            scrobble::configure_lastfm,
+            scrobble::disconnect_lastfm,
+            scrobble::configure_listenbrainz,
+            scrobble::disconnect_listenbrainz,
+            scrobble::flush_scrobbles,
+            scrobble::get_scrobble_queue,
+            settings::get_cover_patterns,
+            settings::set_cover_patterns,
+            settings::reset_cover_patterns,
+            settings::get_artist_separators,
+            settings::set_artist_separators,
+            settings::reset_artist_separators,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+
+use rusqlite::Connection;
+
+pub(crate) const MIN_CONFIDENCE: f64 = 0.6;
+
+const TITLE_WEIGHT: f64 = 0.4;
+const ARTIST_WEIGHT: f64 = 0.3;
+const ALBUM_WEIGHT: f64 = 0.15;
+const DURATION_WEIGHT: f64 = 0.15;
+
+#[derive(Debug, Default)]
+pub(crate) struct TrackQuery {
+    pub artist: Option<String>,
+    pub title: String,
+    pub album: Option<String>,
+    pub duration: Option<u64>,
+}
+
+#[derive(Debug)]
+struct Candidate {
+    file_path: String,
+    title: String,
+    artist: String,
+    album_artist: String,
+    album_title: String,
+    duration: u64,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SongMatch {
+    pub file_path: String,
+    pub confidence: f64,
+}
+
+pub(crate) struct LibraryIndex {
+    candidates: Vec<Candidate>,
+    by_title: HashMap<String, Vec<usize>>,
+    paths: HashMap<String, String>,
+}
+
+pub(crate) fn normalize(value: &str) -> String {
+    let cleaned = value
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>();
+    let words = cleaned.split_whitespace().collect::<Vec<_>>();
+
+    match words.as_slice() {
+        ["the", rest @ ..] if !rest.is_empty() => rest.join(" "),
+        _ => words.join(" "),
+    }
+}
+
+fn base_title(value: &str) -> String {
+    let end = value.find(['(', '[']).unwrap_or(value.len());
+    let base = normalize(&value[..end]);
+
+    if base.is_empty() {
+        normalize(value)
+    } else {
+        base
+    }
+}
+
+fn score_text(query: Option<&str>, values: &[&str], weight: f64) -> f64 {
+    let Some(query) = query.map(normalize).filter(|q| !q.is_empty()) else {
+        // Unknown fields don't confirm a match, so a title alone never reaches `MIN_CONFIDENCE`
+        return 0.0;
+    };
+
+    let mut best = 0.0;
+    for value in values.iter().map(|v| normalize(v)) {
+        if value == query {
+            return weight;
+        }
+        if !value.is_empty() && (value.contains(&query) || query.contains(&value)) {
+            best = weight / 2.0;
+        }
+    }
+
+    best
+}
+
+fn score_duration(query: Option<u64>, duration: u64) -> f64 {
+    let Some(query) = query.filter(|d| *d > 0 && duration > 0) else {
+        return 0.0;
+    };
+
+    match query.abs_diff(duration) {
+        0..=2000 => DURATION_WEIGHT,
+        2001..=5000 => DURATION_WEIGHT * 2.0 / 3.0,
+        5001..=10000 => DURATION_WEIGHT / 3.0,
+        _ => 0.0,
+    }
+}
+
+impl Candidate {
+    fn score(&self, query: &TrackQuery) -> f64 {
+        let title = if normalize(&self.title) == normalize(&query.title) {
+            TITLE_WEIGHT
+        } else {
+            TITLE_WEIGHT * 0.75
+        };
+        let artist = score_text(query.artist.as_deref(), &[&self.artist, &self.album_artist], ARTIST_WEIGHT);
+        let album = score_text(query.album.as_deref(), &[&self.album_title], ALBUM_WEIGHT);
+        let duration = score_duration(query.duration, self.duration);
+
+        title + artist + album + duration
+    }
+}
+
+impl LibraryIndex {
+    pub fn load(conn: &Connection) -> Result<Self, rusqlite::Error> {
+        let mut stmt = conn.prepare("SELECT file_path, title, artist, album_artist, album_title, duration FROM song")?;
+        let mut rows = stmt.query([])?;
+        let mut candidates = Vec::new();
+        let mut by_title: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut paths = HashMap::new();
+
+        while let Some(row) = rows.next()? {
+            let candidate = Candidate {
+                file_path: row.get(0)?,
+                title: row.get(1)?,
+                artist: row.get(2)?,
+                album_artist: row.get(3)?,
+                album_title: row.get(4)?,
+                duration: row.get(5)?,
+            };
+            let index = candidates.len();
+            let exact = normalize(&candidate.title);
+            let base = base_title(&candidate.title);
+
+            if base != exact {
+                by_title.entry(base).or_default().push(index);
+            }
+            by_title.entry(exact).or_default().push(index);
+            paths.insert(candidate.file_path.to_lowercase(), candidate.file_path.clone());
+            candidates.push(candidate);
+        }
+
+        Ok(LibraryIndex { candidates, by_title, paths })
+    }
+
+    pub fn find_path(&self, path: &str) -> Option<String> {
+        self.paths.get(&path.to_lowercase()).cloned()
+    }
+
+    pub fn best_match(&self, query: &TrackQuery) -> Option<SongMatch> {
+        let mut indices: Vec<usize> = Vec::new();
+        for key in [normalize(&query.title), base_title(&query.title)] {
+            indices.extend(self.by_title.get(&key).into_iter().flatten());
+        }
+        indices.sort_unstable();
+        indices.dedup();
+
+        indices
+            .into_iter()
+            .map(|i| &self.candidates[i])
+            .map(|c| SongMatch {
+                file_path: c.file_path.clone(),
+                confidence: (c.score(query) * 100.0).round() / 100.0,
+            })
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+    }
+
+    pub fn find_by_tags(&self, query: &TrackQuery) -> Option<SongMatch> {
+        self.best_match(query).filter(|m| m.confidence >= MIN_CONFIDENCE)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn library(songs: &[(&str, &str, &str, &str, u64)]) -> LibraryIndex {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(include_str!("../db/schema.sql")).unwrap();
+        for (file_path, title, artist, album_title, duration) in songs {
+            conn.execute(
+                "INSERT INTO song (file_path, title, artist, album_title, album_artist, duration) VALUES (?1, ?2, ?3, ?4, ?3, ?5)",
+                rusqlite::params![file_path, title, artist, album_title, duration],
+            )
+            .unwrap();
+        }
+        LibraryIndex::load(&conn).unwrap()
+    }
+
+    fn query(artist: Option<&str>, title: &str, album: Option<&str>, duration: Option<u64>) -> TrackQuery {
+        TrackQuery {
+            artist: artist.map(String::from),
+            title: title.into(),
+            album: album.map(String::from),
+            duration,
+        }
+    }
+
+    #[test]
+    fn normalize_ignores_case_punctuation_and_leading_article() {
+        assert_eq!(normalize("Don't Stop Me Now"), "don t stop me now");
+        assert_eq!(normalize("The Beatles"), "beatles");
+        assert_eq!(normalize("The"), "the");
+        assert_eq!(base_title("Yesterday (Remastered 2009)"), "yesterday");
+    }
+
+    #[test]
+    fn title_alone_is_not_confident() {
+        let library = library(&[("/music/a.flac", "Intro", "Artist", "Album", 60_000)]);
+        let song_match = library.best_match(&query(None, "Intro", None, None)).unwrap();
+
+        assert!(song_match.confidence < MIN_CONFIDENCE);
+        assert!(library.find_by_tags(&query(None, "Intro", None, None)).is_none());
+    }
+
+    #[test]
+    fn title_and_artist_are_confident() {
+        let library = library(&[("/music/a.flac", "Intro", "Artist", "Album", 60_000)]);
+
+        let song_match = library.find_by_tags(&query(Some("artist"), "intro", None, None)).unwrap();
+        assert_eq!(song_match.file_path, "/music/a.flac");
+        assert_eq!(song_match.confidence, 0.7);
+    }
+
+    #[test]
+    fn wrong_artist_is_not_confident() {
+        let library = library(&[("/music/a.flac", "Intro", "Artist", "Album", 60_000)]);
+
+        assert!(library.find_by_tags(&query(Some("Someone Else"), "Intro", Some("Album"), None)).is_none());
+    }
+
+    #[test]
+    fn best_match_prefers_matching_album_and_duration() {
+        let library = library(&[
+            ("/music/live.flac", "Song (Live)", "Artist", "Live Album", 300_000),
+            ("/music/studio.flac", "Song", "Artist", "Studio Album", 200_000),
+        ]);
+
+        let song_match = library.find_by_tags(&query(Some("Artist"), "Song", Some("Studio Album"), Some(201_000))).unwrap();
+        assert_eq!(song_match.file_path, "/music/studio.flac");
+        assert_eq!(song_match.confidence, 1.0);
+    }
+
+    #[test]
+    fn find_path_ignores_case() {
+        let library = library(&[("/Music/A.flac", "Intro", "Artist", "Album", 60_000)]);
+
+        assert_eq!(library.find_path("/music/a.flac").as_deref(), Some("/Music/A.flac"));
+        assert_eq!(library.find_path("/music/b.flac"), None);
+    }
+}
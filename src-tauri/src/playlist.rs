@@ -0,0 +1,231 @@
+use std::error::Error;
+
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+
+use crate::db::{get_db_connection, query_row, query_row_params};
+
+pub(crate) fn create_playlist_row(conn: &Connection, name: &str) -> Result<i64, rusqlite::Error> {
+    conn.execute("INSERT INTO playlist (name) VALUES (?1)", params![name])?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn touch_playlist(conn: &Connection, id: i64) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "UPDATE playlist SET updated_at = strftime('%s', 'now') WHERE id = ?1",
+        params![id],
+    )?;
+    Ok(())
+}
+
+fn playlist_length(conn: &Connection, id: i64) -> Result<i64, rusqlite::Error> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM playlist_entry WHERE playlist_id = ?1",
+        params![id],
+        |row| row.get(0),
+    )
+}
+
+fn renumber_entries(tx: &Transaction, id: i64) -> Result<(), rusqlite::Error> {
+    tx.execute(
+        "UPDATE playlist_entry SET position = (
+            SELECT COUNT(*) FROM playlist_entry AS other
+            WHERE other.playlist_id = playlist_entry.playlist_id
+            AND (other.position < playlist_entry.position
+                OR (other.position = playlist_entry.position AND other.id < playlist_entry.id))
+        )
+        WHERE playlist_id = ?1",
+        params![id],
+    )?;
+    Ok(())
+}
+
+pub(crate) fn insert_entries(
+    tx: &Transaction,
+    id: i64,
+    file_paths: &[String],
+    position: Option<i64>,
+) -> Result<(), Box<dyn Error>> {
+    let length = playlist_length(tx, id)?;
+    let start = position.unwrap_or(length).clamp(0, length);
+    let count = file_paths.len() as i64;
+
+    tx.execute(
+        "UPDATE playlist_entry SET position = position + ?3 WHERE playlist_id = ?1 AND position >= ?2",
+        params![id, start, count],
+    )?;
+
+    let mut stmt = tx.prepare_cached(
+        "INSERT INTO playlist_entry (playlist_id, position, file_path) VALUES (?1, ?2, ?3)",
+    )?;
+    for (i, file_path) in file_paths.iter().enumerate() {
+        stmt.execute(params![id, start + i as i64, file_path])?;
+    }
+
+    touch_playlist(tx, id)?;
+    Ok(())
+}
+
+fn playlist_exists(conn: &Connection, id: i64) -> Result<bool, rusqlite::Error> {
+    conn.query_row("SELECT 1 FROM playlist WHERE id = ?1", params![id], |_| Ok(()))
+        .optional()
+        .map(|row| row.is_some())
+}
+
+#[tauri::command]
+pub fn create_playlist(name: String, app: tauri::AppHandle) -> Result<String, String> {
+    let conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    let id = create_playlist_row(&conn, &name).map_err(|e| e.to_string())?;
+
+    Ok(id.to_string())
+}
+
+#[tauri::command]
+pub fn rename_playlist(id: i64, name: String, app: tauri::AppHandle) -> Result<String, String> {
+    let conn = get_db_connection(app).map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "UPDATE playlist SET name = ?2, updated_at = strftime('%s', 'now') WHERE id = ?1",
+        params![id, name],
+    ).map_err(|e| e.to_string())?;
+
+    Ok("Playlist renamed".into())
+}
+
+#[tauri::command]
+pub fn delete_playlist(id: i64, app: tauri::AppHandle) -> Result<String, String> {
+    let mut conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    tx.execute("DELETE FROM playlist_entry WHERE playlist_id = ?1", params![id]).map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM playlist WHERE id = ?1", params![id]).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok("Playlist deleted".into())
+}
+
+#[tauri::command]
+pub fn duplicate_playlist(id: i64, name: Option<String>, app: tauri::AppHandle) -> Result<String, String> {
+    let mut conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let original: String = tx
+        .query_row("SELECT name FROM playlist WHERE id = ?1", params![id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let name = name.unwrap_or(format!("{} (copy)", original));
+    let new_id = create_playlist_row(&tx, &name).map_err(|e| e.to_string())?;
+
+    tx.execute(
+        "INSERT INTO playlist_entry (playlist_id, position, file_path)
+        SELECT ?2, position, file_path FROM playlist_entry WHERE playlist_id = ?1",
+        params![id, new_id],
+    ).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(new_id.to_string())
+}
+
+#[tauri::command]
+pub fn get_all_playlists(app: tauri::AppHandle) -> Result<String, String> {
+    query_row(
+        "SELECT
+            playlist.*,
+            COUNT(playlist_entry.id) AS song_count,
+            COALESCE(SUM(song.duration), 0) AS duration
+        FROM playlist
+        LEFT JOIN playlist_entry ON playlist_entry.playlist_id = playlist.id
+        LEFT JOIN song ON song.file_path = playlist_entry.file_path
+        GROUP BY playlist.id ORDER BY playlist.name COLLATE NOCASE",
+        app
+    )
+}
+
+#[tauri::command]
+pub fn get_playlist_songs(id: i64, app: tauri::AppHandle) -> Result<String, String> {
+    query_row_params(
+        "SELECT song_with_stats.*, playlist_entry.id AS entry_id, playlist_entry.position
+        FROM playlist_entry
+        JOIN song_with_stats ON song_with_stats.file_path = playlist_entry.file_path
+        WHERE playlist_entry.playlist_id = ?1
+        ORDER BY playlist_entry.position",
+        params![id],
+        app
+    )
+}
+
+#[tauri::command]
+pub fn add_to_playlist(
+    id: i64,
+    file_paths: Vec<String>,
+    position: Option<i64>,
+    app: tauri::AppHandle
+) -> Result<String, String> {
+    let mut conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    if !playlist_exists(&tx, id).map_err(|e| e.to_string())? {
+        return Err(format!("Playlist {} does not exist", id));
+    }
+
+    insert_entries(&tx, id, &file_paths, position).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(format!("Added {} songs to playlist", file_paths.len()))
+}
+
+#[tauri::command]
+pub fn remove_from_playlist(id: i64, entry_ids: Vec<i64>, app: tauri::AppHandle) -> Result<String, String> {
+    let mut conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    for entry_id in &entry_ids {
+        tx.execute(
+            "DELETE FROM playlist_entry WHERE id = ?1 AND playlist_id = ?2",
+            params![entry_id, id],
+        ).map_err(|e| e.to_string())?;
+    }
+
+    renumber_entries(&tx, id).map_err(|e| e.to_string())?;
+    touch_playlist(&tx, id).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(format!("Removed {} songs from playlist", entry_ids.len()))
+}
+
+#[tauri::command]
+pub fn move_playlist_entry(id: i64, entry_id: i64, position: i64, app: tauri::AppHandle) -> Result<String, String> {
+    let mut conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let current: i64 = tx
+        .query_row(
+            "SELECT position FROM playlist_entry WHERE id = ?1 AND playlist_id = ?2",
+            params![entry_id, id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    let last = playlist_length(&tx, id).map_err(|e| e.to_string())? - 1;
+    let target = position.clamp(0, last);
+
+    if target > current {
+        tx.execute(
+            "UPDATE playlist_entry SET position = position - 1
+            WHERE playlist_id = ?1 AND position > ?2 AND position <= ?3",
+            params![id, current, target],
+        ).map_err(|e| e.to_string())?;
+    } else if target < current {
+        tx.execute(
+            "UPDATE playlist_entry SET position = position + 1
+            WHERE playlist_id = ?1 AND position >= ?3 AND position < ?2",
+            params![id, current, target],
+        ).map_err(|e| e.to_string())?;
+    }
+
+    tx.execute(
+        "UPDATE playlist_entry SET position = ?2 WHERE id = ?1",
+        params![entry_id, target],
+    ).map_err(|e| e.to_string())?;
+    touch_playlist(&tx, id).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok("Playlist entry moved".into())
+}
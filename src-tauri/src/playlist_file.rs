@@ -0,0 +1,785 @@
+use std::{
+    error::Error,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use quick_xml::{escape::escape, events::Event, Reader};
+use rusqlite::{params, Connection};
+use serde_json::json;
+
+use crate::db::get_db_connection;
+use crate::matching::{LibraryIndex, SongMatch, TrackQuery};
+use crate::playlist::{create_playlist_row, insert_entries};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PlaylistFormat {
+    M3u,
+    Pls,
+    Xspf,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct PlaylistItem {
+    pub location: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration: Option<u64>,
+}
+
+struct ExportItem {
+    file_path: String,
+    title: String,
+    artist: String,
+    album_title: String,
+    track_number: u16,
+    duration: u64,
+}
+
+impl PlaylistFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        Self::from_name(&extension)
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "m3u" | "m3u8" => Some(PlaylistFormat::M3u),
+            "pls" => Some(PlaylistFormat::Pls),
+            "xspf" => Some(PlaylistFormat::Xspf),
+            _ => None,
+        }
+    }
+}
+
+fn read_text(path: &Path) -> Result<String, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let text = String::from_utf8_lossy(&bytes);
+
+    Ok(text.trim_start_matches('\u{feff}').to_string())
+}
+
+fn split_display_title(display: &str) -> (Option<String>, Option<String>) {
+    let display = display.trim();
+    if display.is_empty() {
+        return (None, None);
+    }
+
+    match display.split_once(" - ") {
+        Some((artist, title)) => (Some(artist.trim().to_string()), Some(title.trim().to_string())),
+        None => (None, Some(display.to_string())),
+    }
+}
+
+fn parse_duration(value: &str) -> Option<u64> {
+    value.trim().parse::<i64>().ok().filter(|d| *d > 0).map(|d| d as u64)
+}
+
+fn parse_seconds(value: &str) -> Option<u64> {
+    parse_duration(value).map(|secs| secs * 1000)
+}
+
+fn to_seconds(ms: u64) -> u64 {
+    (ms + 500) / 1000
+}
+
+pub(crate) fn parse_m3u(text: &str) -> Vec<PlaylistItem> {
+    let mut items = Vec::new();
+    let mut pending = PlaylistItem::default();
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            let (duration, display) = info.split_once(',').unwrap_or((info, ""));
+            let (artist, title) = split_display_title(display);
+            // Attributes such as tvg-id="..." may follow the duration
+            let duration = duration.split_whitespace().next().unwrap_or_default();
+
+            pending.duration = parse_seconds(duration);
+            pending.artist = artist;
+            pending.title = title;
+            continue;
+        }
+
+        if line.starts_with('#') {
+            continue;
+        }
+
+        pending.location = line.to_string();
+        items.push(std::mem::take(&mut pending));
+    }
+
+    items
+}
+
+pub(crate) fn parse_pls(text: &str) -> Vec<PlaylistItem> {
+    let mut entries: Vec<(u32, PlaylistItem)> = Vec::new();
+
+    for line in text.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else { continue };
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+
+        let (field, number) = match key.find(|c: char| c.is_ascii_digit()) {
+            Some(i) => (&key[..i], &key[i..]),
+            None => continue,
+        };
+        let Ok(number) = number.parse::<u32>() else { continue };
+
+        let index = match entries.iter().position(|(n, _)| *n == number) {
+            Some(index) => index,
+            None => {
+                entries.push((number, PlaylistItem::default()));
+                entries.len() - 1
+            }
+        };
+        let item = &mut entries[index].1;
+
+        match field {
+            "file" => item.location = value.to_string(),
+            "title" => {
+                let (artist, title) = split_display_title(value);
+                item.artist = artist;
+                item.title = title;
+            }
+            "length" => item.duration = parse_seconds(value),
+            _ => {}
+        }
+    }
+
+    entries.sort_by_key(|(number, _)| *number);
+    entries
+        .into_iter()
+        .map(|(_, item)| item)
+        .filter(|item| !item.location.is_empty())
+        .collect()
+}
+
+pub(crate) fn parse_xspf(text: &str) -> Result<Vec<PlaylistItem>, Box<dyn Error>> {
+    let mut reader = Reader::from_str(text);
+    reader.trim_text(true);
+
+    let mut items = Vec::new();
+    let mut current: Option<PlaylistItem> = None;
+    let mut element = Vec::new();
+
+    loop {
+        let text = match reader.read_event()? {
+            Event::Start(e) => {
+                element = e.local_name().as_ref().to_vec();
+                if element == b"track" {
+                    current = Some(PlaylistItem::default());
+                }
+                continue;
+            }
+            Event::End(e) => {
+                if e.local_name().as_ref() == b"track" {
+                    items.extend(current.take());
+                }
+                element.clear();
+                continue;
+            }
+            Event::Text(t) => t.unescape()?.trim().to_string(),
+            Event::CData(c) => String::from_utf8_lossy(&c.into_inner()).trim().to_string(),
+            Event::Eof => break,
+            _ => continue,
+        };
+
+        let Some(item) = current.as_mut() else { continue };
+        match element.as_slice() {
+            // Only the first location is used, the rest are alternatives
+            b"location" if item.location.is_empty() => item.location = text,
+            b"title" => item.title = Some(text),
+            b"creator" => item.artist = Some(text),
+            b"album" => item.album = Some(text),
+            b"duration" => item.duration = parse_duration(&text),
+            _ => {}
+        }
+    }
+
+    Ok(items)
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn path_to_file_url(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut url = String::from(if path.starts_with('/') { "file://" } else { "file:///" });
+
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' | b':' => url.push(byte as char),
+            _ => url += &format!("%{:02X}", byte),
+        }
+    }
+
+    url
+}
+
+pub(crate) fn location_to_path(location: &str) -> String {
+    let Some(rest) = location.strip_prefix("file://") else {
+        return location.to_string();
+    };
+    // file:///C:/Music/... on Windows, file:///home/... elsewhere
+    let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+    let decoded = percent_decode(rest);
+    let bytes = decoded.as_bytes();
+
+    if bytes.len() > 2 && bytes[0] == b'/' && bytes[2] == b':' {
+        decoded[1..].to_string()
+    } else {
+        decoded
+    }
+}
+
+fn is_windows_absolute(location: &str) -> bool {
+    let bytes = location.as_bytes();
+    (bytes.len() > 2 && bytes[1] == b':' && (bytes[2] == b'\\' || bytes[2] == b'/'))
+        || location.starts_with("\\\\")
+}
+
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+
+    normalized
+}
+
+fn resolve_location(location: &str, base_dir: &Path) -> PathBuf {
+    let location = location_to_path(location);
+    let path = if std::path::MAIN_SEPARATOR == '/' && !is_windows_absolute(&location) {
+        PathBuf::from(location.replace('\\', "/"))
+    } else {
+        PathBuf::from(&location)
+    };
+
+    if path.is_absolute() || is_windows_absolute(&location) {
+        normalize_path(&path)
+    } else {
+        normalize_path(&base_dir.join(path))
+    }
+}
+
+fn query_from_file_name(location: &str) -> Option<TrackQuery> {
+    // Split on both separators since Windows playlists get imported on other systems too
+    let file_name = location.rsplit(['/', '\\']).next()?;
+    let stem = file_name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(file_name);
+    let stem = stem.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.' || c == ' ' || c == '-' || c == '_');
+    let (artist, title) = split_display_title(stem);
+
+    Some(TrackQuery {
+        artist,
+        title: title?,
+        album: None,
+        duration: None,
+    })
+}
+
+pub(crate) enum Resolution {
+    Path(String),
+    Tags(SongMatch),
+    Unresolved,
+}
+
+fn resolve_by_tags(library: &LibraryIndex, item: &PlaylistItem) -> Option<SongMatch> {
+    let query = match &item.title {
+        Some(title) => TrackQuery {
+            artist: item.artist.clone(),
+            title: title.clone(),
+            album: item.album.clone(),
+            duration: item.duration,
+        },
+        None => query_from_file_name(&location_to_path(&item.location))?,
+    };
+
+    library.find_by_tags(&query)
+}
+
+pub(crate) fn resolve_item(library: &LibraryIndex, item: &PlaylistItem, base_dir: &Path, prefer_tags: bool) -> Resolution {
+    if prefer_tags && item.title.is_some() {
+        if let Some(song_match) = resolve_by_tags(library, item) {
+            return Resolution::Tags(song_match);
+        }
+    }
+
+    let path = resolve_location(&item.location, base_dir);
+    let path_string = path.to_string_lossy().to_string();
+
+    if let Some(file_path) = library.find_path(&path_string) {
+        return Resolution::Path(file_path);
+    }
+
+    if let Ok(canonical) = fs::canonicalize(&path) {
+        if let Some(file_path) = library.find_path(&canonical.to_string_lossy()) {
+            return Resolution::Path(file_path);
+        }
+    }
+
+    match resolve_by_tags(library, item) {
+        Some(song_match) => Resolution::Tags(song_match),
+        None => Resolution::Unresolved,
+    }
+}
+
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path_components = path.components().collect::<Vec<_>>();
+    let base_components = base.components().collect::<Vec<_>>();
+
+    match (path_components.first(), base_components.first()) {
+        (Some(a), Some(b)) if a == b => {}
+        _ => return path.to_path_buf(),
+    }
+
+    let common = path_components
+        .iter()
+        .zip(base_components.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..base_components.len() {
+        relative.push("..");
+    }
+    for component in &path_components[common..] {
+        relative.push(component.as_os_str());
+    }
+
+    relative
+}
+
+fn write_m3u(items: &[ExportItem], base_dir: &Path) -> String {
+    let mut output = String::from("#EXTM3U\n");
+
+    for item in items {
+        let location = relative_path(Path::new(&item.file_path), base_dir);
+        output += &format!("#EXTINF:{},{} - {}\n", to_seconds(item.duration), item.artist, item.title);
+        output += &format!("{}\n", location.to_string_lossy());
+    }
+
+    output
+}
+
+fn write_pls(items: &[ExportItem], base_dir: &Path) -> String {
+    let mut output = String::from("[playlist]\n");
+
+    for (i, item) in items.iter().enumerate() {
+        let number = i + 1;
+        let location = relative_path(Path::new(&item.file_path), base_dir);
+        output += &format!("File{}={}\n", number, location.to_string_lossy());
+        output += &format!("Title{}={} - {}\n", number, item.artist, item.title);
+        output += &format!("Length{}={}\n", number, to_seconds(item.duration));
+    }
+
+    output += &format!("NumberOfEntries={}\nVersion=2\n", items.len());
+    output
+}
+
+fn write_xspf(items: &[ExportItem], title: &str) -> String {
+    let mut output = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    output += "<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n";
+    output += &format!("  <title>{}</title>\n  <trackList>\n", escape(title));
+
+    for item in items {
+        output += "    <track>\n";
+        output += &format!("      <location>{}</location>\n", escape(&path_to_file_url(&item.file_path)));
+        output += &format!("      <title>{}</title>\n", escape(&item.title));
+        output += &format!("      <creator>{}</creator>\n", escape(&item.artist));
+        if !item.album_title.is_empty() {
+            output += &format!("      <album>{}</album>\n", escape(&item.album_title));
+        }
+        if item.track_number > 0 {
+            output += &format!("      <trackNum>{}</trackNum>\n", item.track_number);
+        }
+        if item.duration > 0 {
+            output += &format!("      <duration>{}</duration>\n", item.duration);
+        }
+        output += "    </track>\n";
+    }
+
+    output += "  </trackList>\n</playlist>\n";
+    output
+}
+
+fn load_export_items(conn: &Connection, file_paths: &[String]) -> Result<Vec<ExportItem>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT title, artist, album_title, track_number, duration FROM song WHERE file_path = ?1",
+    )?;
+    let mut items = Vec::new();
+
+    for file_path in file_paths {
+        let mut rows = stmt.query(params![file_path])?;
+        let item = match rows.next()? {
+            Some(row) => ExportItem {
+                file_path: file_path.clone(),
+                title: row.get(0)?,
+                artist: row.get(1)?,
+                album_title: row.get(2)?,
+                track_number: row.get(3)?,
+                duration: row.get(4)?,
+            },
+            // Keep songs that aren't in the library, with the file name as the title
+            None => ExportItem {
+                file_path: file_path.clone(),
+                title: Path::new(file_path).file_stem().unwrap_or_default().to_string_lossy().to_string(),
+                artist: String::from("Unknown"),
+                album_title: String::new(),
+                track_number: 0,
+                duration: 0,
+            },
+        };
+        items.push(item);
+    }
+
+    Ok(items)
+}
+
+fn export_to_file(
+    conn: &Connection,
+    file_paths: &[String],
+    title: &str,
+    dest: &Path,
+    format: Option<String>,
+) -> Result<String, Box<dyn Error>> {
+    let format = match format {
+        Some(name) => PlaylistFormat::from_name(&name).ok_or(format!("Unsupported playlist format: {}", name))?,
+        None => PlaylistFormat::from_path(dest).ok_or("Could not tell playlist format from file extension")?,
+    };
+    let base_dir = dest.parent().ok_or("Failed to get parent directory")?;
+    let items = load_export_items(conn, file_paths)?;
+
+    let contents = match format {
+        PlaylistFormat::M3u => write_m3u(&items, base_dir),
+        PlaylistFormat::Pls => write_pls(&items, base_dir),
+        PlaylistFormat::Xspf => write_xspf(&items, title),
+    };
+    fs::write(dest, contents)?;
+
+    Ok(format!("Exported {} songs to {}", items.len(), dest.to_string_lossy()))
+}
+
+pub(crate) fn read_playlist_items(path: &Path, format: PlaylistFormat) -> Result<Vec<PlaylistItem>, Box<dyn Error>> {
+    let text = read_text(path)?;
+
+    Ok(match format {
+        PlaylistFormat::M3u => parse_m3u(&text),
+        PlaylistFormat::Pls => parse_pls(&text),
+        PlaylistFormat::Xspf => parse_xspf(&text)?,
+    })
+}
+
+#[tauri::command]
+pub fn import_playlist(path: &Path, name: Option<String>, app: tauri::AppHandle) -> Result<String, String> {
+    let format = PlaylistFormat::from_path(path).ok_or("Unsupported playlist format")?;
+    let items = read_playlist_items(path, format).map_err(|e| e.to_string())?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    let name = name.unwrap_or(path.file_stem().unwrap_or_default().to_string_lossy().to_string());
+
+    let mut conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    let library = LibraryIndex::load(&conn).map_err(|e| e.to_string())?;
+
+    let mut file_paths = Vec::new();
+    let mut matched_by_tags = 0;
+    let mut entries = Vec::new();
+    let mut unresolved = Vec::new();
+
+    for item in &items {
+        let entry = json!({
+            "location": item.location,
+            "artist": item.artist,
+            "title": item.title,
+            "album": item.album,
+        });
+
+        let (file_path, confidence) = match resolve_item(&library, item, base_dir, format == PlaylistFormat::Xspf) {
+            Resolution::Path(file_path) => (file_path, 1.0),
+            Resolution::Tags(song_match) => {
+                matched_by_tags += 1;
+                (song_match.file_path, song_match.confidence)
+            }
+            Resolution::Unresolved => {
+                unresolved.push(entry);
+                continue;
+            }
+        };
+
+        let mut entry = entry;
+        entry["file_path"] = json!(file_path);
+        entry["confidence"] = json!(confidence);
+        entries.push(entry);
+        file_paths.push(file_path);
+    }
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let id = create_playlist_row(&tx, &name).map_err(|e| e.to_string())?;
+    insert_entries(&tx, id, &file_paths, None).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    let report = json!({
+        "playlist_id": id,
+        "name": name,
+        "imported": file_paths.len(),
+        "matched_by_tags": matched_by_tags,
+        "entries": entries,
+        "unresolved": unresolved,
+    });
+
+    Ok(report.to_string())
+}
+
+#[tauri::command]
+pub fn export_playlist(id: i64, dest: &Path, format: Option<String>, app: tauri::AppHandle) -> Result<String, String> {
+    let conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    let name: String = conn
+        .query_row("SELECT name FROM playlist WHERE id = ?1", params![id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let mut stmt = conn
+        .prepare("SELECT file_path FROM playlist_entry WHERE playlist_id = ?1 ORDER BY position")
+        .map_err(|e| e.to_string())?;
+    let file_paths = stmt
+        .query_map(params![id], |row| row.get(0))
+        .and_then(|rows| rows.collect::<Result<Vec<String>, _>>())
+        .map_err(|e| e.to_string())?;
+
+    export_to_file(&conn, &file_paths, &name, dest, format).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn export_queue(file_paths: Vec<String>, dest: &Path, format: Option<String>, app: tauri::AppHandle) -> Result<String, String> {
+    let conn = get_db_connection(app).map_err(|e| e.to_string())?;
+
+    export_to_file(&conn, &file_paths, "Queue", dest, format).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_m3u_reads_extinf_and_skips_comments() {
+        let text = "#EXTM3U\n\
+            #EXTINF:215 tvg-id=\"x\",Artist - Title\n\
+            Music/01 Title.mp3\n\
+            \n\
+            # a comment\n\
+            #EXTINF:-1,Just A Title\n\
+            http://example.com/stream\n\
+            /plain/path.flac\n";
+        let items = parse_m3u(text);
+
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].location, "Music/01 Title.mp3");
+        assert_eq!(items[0].artist.as_deref(), Some("Artist"));
+        assert_eq!(items[0].title.as_deref(), Some("Title"));
+        assert_eq!(items[0].duration, Some(215_000));
+        assert_eq!(items[1].location, "http://example.com/stream");
+        assert_eq!(items[1].artist, None);
+        assert_eq!(items[1].title.as_deref(), Some("Just A Title"));
+        assert_eq!(items[1].duration, None);
+        // EXTINF only applies to the entry that follows it
+        assert_eq!(items[2].location, "/plain/path.flac");
+        assert_eq!(items[2].title, None);
+    }
+
+    #[test]
+    fn parse_pls_orders_entries_by_number() {
+        let text = "[playlist]\n\
+            File2=/music/b.mp3\n\
+            Title2=Second\n\
+            File1=/music/a.mp3\n\
+            Title1=Artist - First\n\
+            Length1=180\n\
+            Title3=No File\n\
+            NumberOfEntries=3\n\
+            Version=2\n";
+        let items = parse_pls(text);
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].location, "/music/a.mp3");
+        assert_eq!(items[0].artist.as_deref(), Some("Artist"));
+        assert_eq!(items[0].title.as_deref(), Some("First"));
+        assert_eq!(items[0].duration, Some(180_000));
+        assert_eq!(items[1].location, "/music/b.mp3");
+        assert_eq!(items[1].title.as_deref(), Some("Second"));
+        assert_eq!(items[1].duration, None);
+    }
+
+    #[test]
+    fn percent_decode_handles_utf8_and_stray_percent_signs() {
+        assert_eq!(percent_decode("My%20Music/Caf%C3%A9.mp3"), "My Music/Café.mp3");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("50%zz"), "50%zz");
+        assert_eq!(percent_decode("%2"), "%2");
+    }
+
+    #[test]
+    fn location_to_path_converts_file_urls() {
+        assert_eq!(location_to_path("file:///home/me/My%20Music/a.mp3"), "/home/me/My Music/a.mp3");
+        assert_eq!(location_to_path("file://localhost/home/me/a.mp3"), "/home/me/a.mp3");
+        assert_eq!(location_to_path("file:///C:/Music/a.mp3"), "C:/Music/a.mp3");
+        assert_eq!(location_to_path("http://example.com/a%20b.mp3"), "http://example.com/a%20b.mp3");
+        assert_eq!(location_to_path("Music/a.mp3"), "Music/a.mp3");
+    }
+
+    #[test]
+    fn file_urls_round_trip() {
+        let path = "/home/me/Rock & Roll/100% Café.flac";
+        assert_eq!(location_to_path(&path_to_file_url(path)), path);
+    }
+
+    #[test]
+    fn relative_path_walks_up_to_the_common_directory() {
+        assert_eq!(relative_path(Path::new("/music/a/b.mp3"), Path::new("/music/a")), PathBuf::from("b.mp3"));
+        assert_eq!(
+            relative_path(Path::new("/music/a/b.mp3"), Path::new("/music/playlists")),
+            PathBuf::from("../a/b.mp3")
+        );
+        assert_eq!(relative_path(Path::new("/music/a/b.mp3"), Path::new("/")), PathBuf::from("music/a/b.mp3"));
+        assert_eq!(relative_path(Path::new("relative/b.mp3"), Path::new("/music")), PathBuf::from("relative/b.mp3"));
+    }
+
+    #[test]
+    fn resolve_location_joins_relative_entries_to_the_playlist_directory() {
+        assert_eq!(resolve_location("../a/b.mp3", Path::new("/music/playlists")), PathBuf::from("/music/a/b.mp3"));
+        assert_eq!(resolve_location("a\\b.mp3", Path::new("/music")), PathBuf::from("/music/a/b.mp3"));
+        assert_eq!(resolve_location("file:///music/a%20b.mp3", Path::new("/other")), PathBuf::from("/music/a b.mp3"));
+    }
+
+    #[test]
+    fn file_names_without_an_artist_are_not_matched() {
+        let library = crate::matching::tests::library(&[("/music/intro.mp3", "Intro", "Artist", "Album", 60_000)]);
+        let item = PlaylistItem { location: "/elsewhere/01 - Intro.mp3".into(), ..Default::default() };
+
+        assert!(matches!(resolve_item(&library, &item, Path::new("/"), false), Resolution::Unresolved));
+
+        let item = PlaylistItem { location: "/elsewhere/01 - Artist - Intro.mp3".into(), ..Default::default() };
+        assert!(matches!(resolve_item(&library, &item, Path::new("/"), false), Resolution::Tags(m) if m.file_path == "/music/intro.mp3"));
+    }
+
+    #[test]
+    fn parse_xspf_reads_tracks() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <playlist version="1" xmlns="http://xspf.org/ns/0/">
+              <title>Not A Track</title>
+              <trackList>
+                <track>
+                  <location>file:///music/Rock%20&amp;%20Roll.flac</location>
+                  <location>http://example.com/alternative.flac</location>
+                  <title>Rock &amp; Roll</title>
+                  <creator><![CDATA[Led Zeppelin]]></creator>
+                  <album>IV</album>
+                  <duration>220000</duration>
+                </track>
+                <track>
+                  <title>Only Metadata</title>
+                </track>
+              </trackList>
+            </playlist>"#;
+        let items = parse_xspf(text).unwrap();
+
+        assert_eq!(items.len(), 2);
+        // Only the first location is kept, still URL encoded until it is resolved
+        assert_eq!(items[0].location, "file:///music/Rock%20&%20Roll.flac");
+        assert_eq!(location_to_path(&items[0].location), "/music/Rock & Roll.flac");
+        assert_eq!(items[0].title.as_deref(), Some("Rock & Roll"));
+        assert_eq!(items[0].artist.as_deref(), Some("Led Zeppelin"));
+        assert_eq!(items[0].album.as_deref(), Some("IV"));
+        assert_eq!(items[0].duration, Some(220_000));
+        assert_eq!(items[1].location, "");
+        assert_eq!(items[1].title.as_deref(), Some("Only Metadata"));
+    }
+
+    #[test]
+    fn parse_xspf_rejects_malformed_documents() {
+        assert!(parse_xspf("<playlist><trackList><track></trackList></playlist>").is_err());
+    }
+
+    #[test]
+    fn written_xspf_parses_back() {
+        let items = [ExportItem {
+            file_path: "/music/A <B>.flac".into(),
+            title: "Title & More".into(),
+            artist: "Artist".into(),
+            album_title: String::new(),
+            track_number: 3,
+            duration: 180_000,
+        }];
+        let parsed = parse_xspf(&write_xspf(&items, "Mix")).unwrap();
+
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(location_to_path(&parsed[0].location), "/music/A <B>.flac");
+        assert_eq!(parsed[0].title.as_deref(), Some("Title & More"));
+        assert_eq!(parsed[0].artist.as_deref(), Some("Artist"));
+        assert_eq!(parsed[0].album, None);
+        assert_eq!(parsed[0].duration, Some(180_000));
+    }
+
+    fn xspf_item(location: &str, artist: Option<&str>, title: &str) -> PlaylistItem {
+        PlaylistItem {
+            location: location.into(),
+            title: Some(title.into()),
+            artist: artist.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn resolve_item_prefers_tags_for_xspf() {
+        let library = crate::matching::tests::library(&[
+            ("/music/old.flac", "Old", "Artist", "Album", 60_000),
+            ("/music/new.flac", "New", "Artist", "Album", 60_000),
+        ]);
+        // The location points at another song in this library, the tags are what the playlist meant
+        let item = xspf_item("file:///music/old.flac", Some("Artist"), "New");
+
+        assert!(matches!(resolve_item(&library, &item, Path::new("/"), true), Resolution::Tags(m) if m.file_path == "/music/new.flac"));
+        assert!(matches!(resolve_item(&library, &item, Path::new("/"), false), Resolution::Path(p) if p == "/music/old.flac"));
+    }
+
+    #[test]
+    fn resolve_item_falls_back_to_the_location_when_tags_are_not_confident() {
+        let library = crate::matching::tests::library(&[
+            ("/music/a/intro.flac", "Intro", "Artist A", "Album A", 60_000),
+            ("/music/b/intro.flac", "Intro", "Artist B", "Album B", 60_000),
+        ]);
+        let item = xspf_item("file:///music/b/intro.flac", None, "Intro");
+
+        assert!(matches!(resolve_item(&library, &item, Path::new("/"), true), Resolution::Path(p) if p == "/music/b/intro.flac"));
+
+        let item = xspf_item("file:///elsewhere/intro.flac", None, "Intro");
+        assert!(matches!(resolve_item(&library, &item, Path::new("/"), true), Resolution::Unresolved));
+    }
+}
+
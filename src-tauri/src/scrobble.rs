@@ -0,0 +1,832 @@
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{json, Value};
+use tauri::Manager;
+use tauri_plugin_http::reqwest;
+
+use crate::db::{get_db_connection, query_row};
+use crate::settings;
+use crate::tags;
+
+const LASTFM_API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+const LISTENBRAINZ_API_URL: &str = "https://api.listenbrainz.org";
+
+const MIN_TRACK_LENGTH: Duration = Duration::from_secs(30);
+const MAX_LISTEN_REQUIRED: Duration = Duration::from_secs(4 * 60);
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+const MIN_BACKOFF_SECS: u64 = 30;
+const MAX_BACKOFF_SECS: u64 = 6 * 60 * 60;
+
+#[derive(Debug, Clone, Copy)]
+enum Service {
+    LastFm,
+    ListenBrainz,
+}
+
+impl Service {
+    const ALL: [Service; 2] = [Service::LastFm, Service::ListenBrainz];
+
+    fn name(self) -> &'static str {
+        match self {
+            Service::LastFm => "lastfm",
+            Service::ListenBrainz => "listenbrainz",
+        }
+    }
+
+    fn enabled_key(self) -> &'static str {
+        match self {
+            Service::LastFm => "lastfm_scrobbling",
+            Service::ListenBrainz => "listenbrainz_scrobbling",
+        }
+    }
+
+    // Last.fm accepts at most 50 scrobbles per request, ListenBrainz up to 1000 listens
+    fn batch_size(self) -> i64 {
+        match self {
+            Service::LastFm => 50,
+            Service::ListenBrainz => 1000,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LastFmSession {
+    api_key: String,
+    api_secret: String,
+    session_key: String,
+    api_url: String,
+}
+
+#[derive(Clone)]
+pub struct ListenBrainzSession {
+    token: String,
+    api_url: String,
+}
+
+pub struct Scrobbler {
+    lastfm: Mutex<Option<LastFmSession>>,
+    listenbrainz: Mutex<Option<ListenBrainzSession>>,
+    flushing: AtomicBool,
+}
+
+impl Scrobbler {
+    pub fn new() -> Self {
+        Scrobbler {
+            lastfm: Mutex::new(None),
+            listenbrainz: Mutex::new(None),
+            flushing: AtomicBool::new(false),
+        }
+    }
+
+    fn connected(&self, service: Service) -> bool {
+        match service {
+            Service::LastFm => self.lastfm.lock().unwrap().is_some(),
+            Service::ListenBrainz => self.listenbrainz.lock().unwrap().is_some(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum SubmitError {
+    Retry(String),
+    Rejected(String),
+}
+
+impl SubmitError {
+    fn message(&self) -> &str {
+        match self {
+            SubmitError::Retry(message) | SubmitError::Rejected(message) => message,
+        }
+    }
+}
+
+// Last.fm errors caused by the scrobbles themselves: invalid parameters and invalid resource
+const LASTFM_REJECTED_ERRORS: [u64; 2] = [6, 7];
+
+struct Track {
+    artist: String,
+    title: String,
+    album_title: Option<String>,
+    album_artist: Option<String>,
+    track_number: Option<i64>,
+    duration: Option<u64>,
+    recording_mbid: Option<String>,
+    release_mbid: Option<String>,
+    artist_mbids: Option<String>,
+}
+
+struct QueuedScrobble {
+    id: i64,
+    timestamp: u64,
+    track: Track,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn is_scrobblable(duration: Duration, listened: Duration) -> bool {
+    duration > MIN_TRACK_LENGTH && listened >= (duration / 2).min(MAX_LISTEN_REQUIRED)
+}
+
+fn backoff_secs(attempts: u32) -> u64 {
+    MIN_BACKOFF_SECS
+        .saturating_mul(1 << attempts.min(16))
+        .min(MAX_BACKOFF_SECS)
+}
+
+// Last.fm signs requests with the md5 of every parameter except `format`, sorted by name, followed by the secret
+fn api_signature(params: &BTreeMap<String, String>, secret: &str) -> String {
+    let mut payload = params
+        .iter()
+        .filter(|(key, _)| key.as_str() != "format" && key.as_str() != "callback")
+        .map(|(key, value)| format!("{}{}", key, value))
+        .collect::<String>();
+    payload.push_str(secret);
+
+    format!("{:x}", md5::compute(payload))
+}
+
+fn load_track(conn: &Connection, file_path: &str) -> Result<Option<Track>, rusqlite::Error> {
+    let track = conn
+        .query_row(
+            "SELECT artist, title, album_title, album_artist, track_number, duration, recording_mbid, release_mbid, artist_mbids
+            FROM song WHERE file_path = ?1",
+            params![file_path],
+            |row| {
+                Ok(Track {
+                    artist: row.get(0)?,
+                    title: row.get(1)?,
+                    album_title: row.get(2)?,
+                    album_artist: row.get(3)?,
+                    track_number: row.get(4)?,
+                    duration: row.get(5)?,
+                    recording_mbid: row.get(6)?,
+                    release_mbid: row.get(7)?,
+                    artist_mbids: row.get(8)?,
+                })
+            },
+        )
+        .optional()?;
+
+    // Songs scanned before MBIDs were stored have none until they are rescanned, read them from the file instead
+    Ok(track.map(|mut track| {
+        if track.recording_mbid.is_some() || track.release_mbid.is_some() || track.artist_mbids.is_some() {
+            return track;
+        }
+        let ids = tags::read_musicbrainz_ids(Path::new(file_path)).unwrap_or_default();
+        track.recording_mbid = ids.recording;
+        track.release_mbid = ids.release;
+        track.artist_mbids = Some(ids.artists.join(",")).filter(|ids| !ids.is_empty());
+        track
+    }))
+}
+
+pub(crate) fn queue_play(
+    app: &tauri::AppHandle,
+    file_path: &str,
+    started_at: u64,
+    listened: Duration,
+    duration: Option<Duration>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let scrobbler = app.state::<Scrobbler>();
+    let conn = get_db_connection(app.clone())?;
+    let services = Service::ALL
+        .into_iter()
+        .filter(|service| scrobbler.connected(*service) || settings::get_setting(&conn, service.enabled_key()).ok().flatten().is_some())
+        .collect::<Vec<_>>();
+    if services.is_empty() {
+        return Ok(false);
+    }
+
+    let Some(track) = load_track(&conn, file_path)? else { return Ok(false) };
+
+    let duration = duration.or(track.duration.map(Duration::from_millis)).unwrap_or_default();
+    if !is_scrobblable(duration, listened) {
+        return Ok(false);
+    }
+
+    let mut stmt = conn.prepare(
+        "INSERT INTO scrobble_queue (
+            service, file_path, artist, title, album_title, album_artist, track_number, duration,
+            recording_mbid, release_mbid, artist_mbids, timestamp
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+    )?;
+    for service in services {
+        stmt.execute(params![
+            service.name(),
+            file_path,
+            track.artist,
+            track.title,
+            track.album_title,
+            track.album_artist,
+            track.track_number,
+            duration.as_millis() as u64,
+            track.recording_mbid,
+            track.release_mbid,
+            track.artist_mbids,
+            started_at,
+        ])?;
+    }
+
+    Ok(true)
+}
+
+fn next_batch(conn: &Connection, service: Service) -> Result<Vec<QueuedScrobble>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, timestamp, artist, title, album_title, album_artist, track_number, duration,
+            recording_mbid, release_mbid, artist_mbids
+        FROM scrobble_queue
+        WHERE service = ?1 AND rejected = 0 AND next_attempt_at <= ?2
+        ORDER BY timestamp LIMIT ?3",
+    )?;
+    let rows = stmt.query_map(params![service.name(), unix_now(), service.batch_size()], |row| {
+        Ok(QueuedScrobble {
+            id: row.get(0)?,
+            timestamp: row.get(1)?,
+            track: Track {
+                artist: row.get(2)?,
+                title: row.get(3)?,
+                album_title: row.get(4)?,
+                album_artist: row.get(5)?,
+                track_number: row.get(6)?,
+                duration: row.get(7)?,
+                recording_mbid: row.get(8)?,
+                release_mbid: row.get(9)?,
+                artist_mbids: row.get(10)?,
+            },
+        })
+    })?;
+
+    rows.collect()
+}
+
+fn remove_from_queue(conn: &Connection, batch: &[QueuedScrobble]) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare_cached("DELETE FROM scrobble_queue WHERE id = ?1")?;
+    for scrobble in batch {
+        stmt.execute(params![scrobble.id])?;
+    }
+    Ok(())
+}
+
+fn postpone(conn: &Connection, batch: &[QueuedScrobble], error: &str) -> Result<(), rusqlite::Error> {
+    let mut attempts_stmt = conn.prepare_cached("SELECT attempts FROM scrobble_queue WHERE id = ?1")?;
+    let mut update_stmt = conn.prepare_cached(
+        "UPDATE scrobble_queue SET attempts = ?2, next_attempt_at = ?3, last_error = ?4 WHERE id = ?1",
+    )?;
+
+    for scrobble in batch {
+        let attempts: u32 = attempts_stmt.query_row(params![scrobble.id], |row| row.get(0))?;
+        let next_attempt_at = unix_now() + backoff_secs(attempts);
+        update_stmt.execute(params![scrobble.id, attempts + 1, next_attempt_at, error])?;
+    }
+    Ok(())
+}
+
+fn reject(conn: &Connection, batch: &[QueuedScrobble], error: &str) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare_cached("UPDATE scrobble_queue SET rejected = 1, last_error = ?2 WHERE id = ?1")?;
+    for scrobble in batch {
+        stmt.execute(params![scrobble.id, error])?;
+    }
+    Ok(())
+}
+
+fn scrobble_params(session: &LastFmSession, batch: &[QueuedScrobble]) -> BTreeMap<String, String> {
+    let mut params = BTreeMap::new();
+    params.insert("method".to_string(), "track.scrobble".to_string());
+    params.insert("api_key".to_string(), session.api_key.clone());
+    params.insert("sk".to_string(), session.session_key.clone());
+
+    for (i, scrobble) in batch.iter().enumerate() {
+        let track = &scrobble.track;
+        params.insert(format!("artist[{}]", i), track.artist.clone());
+        params.insert(format!("track[{}]", i), track.title.clone());
+        params.insert(format!("timestamp[{}]", i), scrobble.timestamp.to_string());
+        if let Some(album) = track.album_title.as_ref().filter(|a| !a.is_empty()) {
+            params.insert(format!("album[{}]", i), album.clone());
+        }
+        if let Some(album_artist) = track.album_artist.as_ref().filter(|a| !a.is_empty()) {
+            params.insert(format!("albumArtist[{}]", i), album_artist.clone());
+        }
+        if let Some(track_number) = track.track_number.filter(|n| *n > 0) {
+            params.insert(format!("trackNumber[{}]", i), track_number.to_string());
+        }
+        if let Some(duration) = track.duration.filter(|d| *d > 0) {
+            params.insert(format!("duration[{}]", i), (duration / 1000).to_string());
+        }
+        if let Some(mbid) = &track.recording_mbid {
+            params.insert(format!("mbid[{}]", i), mbid.clone());
+        }
+    }
+
+    let signature = api_signature(&params, &session.api_secret);
+    params.insert("api_sig".to_string(), signature);
+    params.insert("format".to_string(), "json".to_string());
+    params
+}
+
+async fn submit_lastfm(session: &LastFmSession, batch: &[QueuedScrobble]) -> Result<u64, SubmitError> {
+    let response = reqwest::Client::new()
+        .post(&session.api_url)
+        .form(&scrobble_params(session, batch))
+        .send()
+        .await
+        .map_err(|e| SubmitError::Retry(e.to_string()))?;
+    let status = response.status();
+    let body = response.text().await.map_err(|e| SubmitError::Retry(e.to_string()))?;
+    let json: Value = serde_json::from_str(&body)
+        .map_err(|_| SubmitError::Retry(format!("Unexpected response from Last.fm ({})", status)))?;
+
+    if let Some(code) = json.get("error") {
+        let message = format!("Last.fm error {}: {}", code, json["message"].as_str().unwrap_or("Unknown error"));
+        return Err(match code.as_u64() {
+            Some(code) if LASTFM_REJECTED_ERRORS.contains(&code) => SubmitError::Rejected(message),
+            _ => SubmitError::Retry(message),
+        });
+    }
+    if !status.is_success() {
+        return Err(SubmitError::Retry(format!("Last.fm responded with {}", status)));
+    }
+
+    let attr = &json["scrobbles"]["@attr"];
+    let count = |value: &Value| value.as_u64().or_else(|| value.as_str()?.parse().ok()).unwrap_or(0);
+    let ignored = count(&attr["ignored"]);
+    if ignored > 0 {
+        println!("Last.fm ignored {} scrobbles", ignored);
+    }
+
+    Ok(count(&attr["accepted"]))
+}
+
+fn listen_json(track: &Track, listened_at: Option<u64>) -> Value {
+    let mut additional_info = json!({
+        "media_player": "Sable",
+        "submission_client": "Sable",
+        "submission_client_version": env!("CARGO_PKG_VERSION"),
+    });
+    if let Some(track_number) = track.track_number.filter(|n| *n > 0) {
+        additional_info["tracknumber"] = json!(track_number);
+    }
+    if let Some(duration) = track.duration.filter(|d| *d > 0) {
+        additional_info["duration_ms"] = json!(duration);
+    }
+    if let Some(mbid) = &track.recording_mbid {
+        additional_info["recording_mbid"] = json!(mbid);
+    }
+    if let Some(mbid) = &track.release_mbid {
+        additional_info["release_mbid"] = json!(mbid);
+    }
+    if let Some(mbids) = &track.artist_mbids {
+        additional_info["artist_mbids"] = json!(mbids.split(',').collect::<Vec<_>>());
+    }
+
+    let mut listen = json!({
+        "track_metadata": {
+            "artist_name": track.artist,
+            "track_name": track.title,
+            "additional_info": additional_info,
+        }
+    });
+    if let Some(album) = track.album_title.as_ref().filter(|a| !a.is_empty()) {
+        listen["track_metadata"]["release_name"] = json!(album);
+    }
+    if let Some(listened_at) = listened_at {
+        listen["listened_at"] = json!(listened_at);
+    }
+
+    listen
+}
+
+async fn submit_listens(session: &ListenBrainzSession, listen_type: &str, payload: Vec<Value>) -> Result<(), SubmitError> {
+    let body = json!({ "listen_type": listen_type, "payload": payload });
+    let response = reqwest::Client::new()
+        .post(format!("{}/1/submit-listens", session.api_url.trim_end_matches('/')))
+        .header("Authorization", format!("Token {}", session.token))
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .map_err(|e| SubmitError::Retry(e.to_string()))?;
+    let status = response.status();
+
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<Value>(&body)
+            .ok()
+            .and_then(|json| json["error"].as_str().map(String::from))
+            .unwrap_or(body);
+        let message = format!("ListenBrainz responded with {}: {}", status, message);
+        return Err(match status {
+            reqwest::StatusCode::BAD_REQUEST => SubmitError::Rejected(message),
+            _ => SubmitError::Retry(message),
+        });
+    }
+
+    Ok(())
+}
+
+async fn submit_listenbrainz(session: &ListenBrainzSession, batch: &[QueuedScrobble]) -> Result<u64, SubmitError> {
+    let payload = batch
+        .iter()
+        .map(|scrobble| listen_json(&scrobble.track, Some(scrobble.timestamp)))
+        .collect::<Vec<_>>();
+    let listen_type = if batch.len() == 1 { "single" } else { "import" };
+
+    submit_listens(session, listen_type, payload).await?;
+    Ok(batch.len() as u64)
+}
+
+pub(crate) async fn flush_queue(app: tauri::AppHandle) -> Result<u64, String> {
+    let scrobbler = app.state::<Scrobbler>();
+    if scrobbler.flushing.swap(true, Ordering::AcqRel) {
+        return Ok(0);
+    }
+
+    let mut submitted = 0;
+    let mut errors = Vec::new();
+    for service in Service::ALL {
+        match flush_batches(&app, service).await {
+            Ok(count) => submitted += count,
+            Err(e) => errors.push(e),
+        }
+    }
+    scrobbler.flushing.store(false, Ordering::Release);
+
+    if errors.is_empty() {
+        Ok(submitted)
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+async fn submit(app: &tauri::AppHandle, service: Service, batch: &[QueuedScrobble]) -> Option<Result<u64, SubmitError>> {
+    let scrobbler = app.state::<Scrobbler>();
+    let lastfm = scrobbler.lastfm.lock().unwrap().clone();
+    let listenbrainz = scrobbler.listenbrainz.lock().unwrap().clone();
+
+    match (service, lastfm, listenbrainz) {
+        (Service::LastFm, Some(session), _) => Some(submit_lastfm(&session, batch).await),
+        (Service::ListenBrainz, _, Some(session)) => Some(submit_listenbrainz(&session, batch).await),
+        _ => None,
+    }
+}
+
+async fn flush_batches(app: &tauri::AppHandle, service: Service) -> Result<u64, String> {
+    let mut submitted = 0;
+
+    loop {
+        let conn = get_db_connection(app.clone()).map_err(|e| e.to_string())?;
+        let batch = next_batch(&conn, service).map_err(|e| e.to_string())?;
+        drop(conn);
+        if batch.is_empty() {
+            return Ok(submitted);
+        }
+
+        let Some(result) = submit(app, service, &batch).await else { return Ok(submitted) };
+        let results = match result {
+            Err(SubmitError::Rejected(_)) if batch.len() > 1 => {
+                let mut results = Vec::with_capacity(batch.len());
+                for scrobble in batch {
+                    let scrobble = vec![scrobble];
+                    let Some(result) = submit(app, service, &scrobble).await else { break };
+                    let retry = matches!(result, Err(SubmitError::Retry(_)));
+                    results.push((scrobble, result));
+                    if retry {
+                        break;
+                    }
+                }
+                results
+            }
+            result => vec![(batch, result)],
+        };
+
+        let conn = get_db_connection(app.clone()).map_err(|e| e.to_string())?;
+        let mut retry_error = None;
+        for (batch, result) in results {
+            match result {
+                Ok(count) => {
+                    // Ignored scrobbles (too old, filtered by Last.fm, ...) will never be accepted, so they are dropped too
+                    remove_from_queue(&conn, &batch).map_err(|e| e.to_string())?;
+                    submitted += count;
+                }
+                Err(SubmitError::Rejected(e)) => {
+                    println!("{} rejected a scrobble: {}", service.name(), e);
+                    reject(&conn, &batch, &e).map_err(|e| e.to_string())?;
+                }
+                Err(SubmitError::Retry(e)) => {
+                    postpone(&conn, &batch, &e).map_err(|e| e.to_string())?;
+                    retry_error = Some(e);
+                }
+            }
+        }
+        if let Some(e) = retry_error {
+            return Err(e);
+        }
+    }
+}
+
+pub(crate) fn spawn_flush(app: &tauri::AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = flush_queue(app).await {
+            println!("Failed to submit scrobbles: {}", e);
+        }
+    });
+}
+
+pub(crate) fn send_playing_now(app: &tauri::AppHandle, file_path: &str) {
+    let Some(session) = app.state::<Scrobbler>().listenbrainz.lock().unwrap().clone() else { return };
+    let track = match get_db_connection(app.clone()).map_err(|e| e.to_string()) {
+        Ok(conn) => load_track(&conn, file_path).map_err(|e| e.to_string()),
+        Err(e) => Err(e),
+    };
+    let Ok(Some(track)) = track else { return };
+
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = submit_listens(&session, "playing_now", vec![listen_json(&track, None)]).await {
+            println!("Failed to send playing now: {}", e.message());
+        }
+    });
+}
+
+pub(crate) async fn run(app: tauri::AppHandle) {
+    loop {
+        tokio::time::sleep(FLUSH_INTERVAL).await;
+        if let Err(e) = flush_queue(app.clone()).await {
+            println!("Failed to submit scrobbles: {}", e);
+        }
+    }
+}
+
+#[tauri::command]
+pub fn configure_lastfm(
+    api_key: String,
+    api_secret: String,
+    session_key: String,
+    api_url: Option<String>,
+    state: tauri::State<Scrobbler>,
+    app: tauri::AppHandle,
+) -> Result<String, String> {
+    *state.lastfm.lock().unwrap() = Some(LastFmSession {
+        api_key,
+        api_secret,
+        session_key,
+        api_url: api_url.filter(|url| !url.is_empty()).unwrap_or(LASTFM_API_URL.to_string()),
+    });
+    set_enabled(&app, Service::LastFm, true)?;
+    spawn_flush(&app);
+
+    Ok("Last.fm scrobbling enabled".into())
+}
+
+fn set_enabled(app: &tauri::AppHandle, service: Service, enabled: bool) -> Result<(), String> {
+    let conn = get_db_connection(app.clone()).map_err(|e| e.to_string())?;
+    let saved = if enabled {
+        settings::set_setting(&conn, service.enabled_key(), "1")
+    } else {
+        settings::remove_setting(&conn, service.enabled_key())
+    };
+    saved.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn disconnect_lastfm(state: tauri::State<Scrobbler>, app: tauri::AppHandle) -> Result<String, String> {
+    *state.lastfm.lock().unwrap() = None;
+    set_enabled(&app, Service::LastFm, false)?;
+    Ok("Last.fm scrobbling disabled".into())
+}
+
+#[tauri::command]
+pub fn configure_listenbrainz(
+    token: String,
+    api_url: Option<String>,
+    state: tauri::State<Scrobbler>,
+    app: tauri::AppHandle,
+) -> Result<String, String> {
+    *state.listenbrainz.lock().unwrap() = Some(ListenBrainzSession {
+        token,
+        api_url: api_url.filter(|url| !url.is_empty()).unwrap_or(LISTENBRAINZ_API_URL.to_string()),
+    });
+    set_enabled(&app, Service::ListenBrainz, true)?;
+    spawn_flush(&app);
+
+    Ok("ListenBrainz scrobbling enabled".into())
+}
+
+#[tauri::command]
+pub fn disconnect_listenbrainz(state: tauri::State<Scrobbler>, app: tauri::AppHandle) -> Result<String, String> {
+    *state.listenbrainz.lock().unwrap() = None;
+    set_enabled(&app, Service::ListenBrainz, false)?;
+    Ok("ListenBrainz scrobbling disabled".into())
+}
+
+#[tauri::command]
+pub async fn flush_scrobbles(app: tauri::AppHandle) -> Result<String, String> {
+    let submitted = flush_queue(app).await?;
+    Ok(format!("Submitted {} scrobbles", submitted))
+}
+
+#[tauri::command]
+pub fn get_scrobble_queue(app: tauri::AppHandle) -> Result<String, String> {
+    query_row("SELECT * FROM scrobble_queue ORDER BY service, timestamp", app)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use super::*;
+
+    fn mock_server(status: &'static str, body: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            // Read until the headers and the body they announce have arrived
+            loop {
+                let read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request);
+                let complete = text.find("\r\n\r\n").is_some_and(|end| {
+                    let length = text[..end]
+                        .lines()
+                        .filter_map(|line| line.split_once(':'))
+                        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+                        .unwrap_or(0);
+                    request.len() >= end + 4 + length
+                });
+                if complete || read == 0 {
+                    break;
+                }
+            }
+
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+            String::from_utf8_lossy(&request).into_owned()
+        });
+
+        (url, handle)
+    }
+
+    fn scrobble(id: i64, timestamp: u64) -> QueuedScrobble {
+        QueuedScrobble {
+            id,
+            timestamp,
+            track: Track {
+                artist: "Artist".into(),
+                title: "Title".into(),
+                album_title: None,
+                album_artist: None,
+                track_number: None,
+                duration: None,
+                recording_mbid: None,
+                release_mbid: None,
+                artist_mbids: None,
+            },
+        }
+    }
+
+    fn lastfm_session(api_url: &str) -> LastFmSession {
+        LastFmSession {
+            api_key: "key".into(),
+            api_secret: "secret".into(),
+            session_key: "SESSION".into(),
+            api_url: api_url.into(),
+        }
+    }
+
+    #[test]
+    fn api_signature_matches_known_vector() {
+        let params = BTreeMap::from([
+            ("api_key".to_string(), "xxxxxxxxxx".to_string()),
+            ("method".to_string(), "auth.getSession".to_string()),
+            ("token".to_string(), "yyyyyy".to_string()),
+            ("format".to_string(), "json".to_string()),
+        ]);
+
+        assert_eq!(api_signature(&params, "ilovecher"), "b87d61da3cda91a8b6746c4aef55d6f8");
+    }
+
+    #[test]
+    fn scrobble_params_are_signed_without_format() {
+        let params = scrobble_params(&lastfm_session(LASTFM_API_URL), &[scrobble(1, 1_700_000_000)]);
+
+        assert_eq!(params["method"], "track.scrobble");
+        assert_eq!(params["artist[0]"], "Artist");
+        assert_eq!(params["track[0]"], "Title");
+        assert_eq!(params["timestamp[0]"], "1700000000");
+        assert_eq!(params["format"], "json");
+        assert!(!params.contains_key("album[0]"));
+        assert_eq!(params["api_sig"], "a9fc350ce178f8853618780d3596df33");
+    }
+
+    #[test]
+    fn scrobble_params_send_duration_in_seconds() {
+        let mut queued = scrobble(1, 1_700_000_000);
+        queued.track.duration = Some(215_000);
+        queued.track.track_number = Some(0);
+        let params = scrobble_params(&lastfm_session(LASTFM_API_URL), &[queued]);
+
+        assert_eq!(params["duration[0]"], "215");
+        assert!(!params.contains_key("trackNumber[0]"));
+    }
+
+    #[test]
+    fn submit_lastfm_counts_accepted_scrobbles() {
+        let (url, server) = mock_server("200 OK", r#"{"scrobbles":{"@attr":{"accepted":2,"ignored":0}}}"#);
+        let batch = [scrobble(1, 1_700_000_000), scrobble(2, 1_700_000_300)];
+
+        let result = tauri::async_runtime::block_on(submit_lastfm(&lastfm_session(&url), &batch));
+        let request = server.join().unwrap();
+
+        assert_eq!(result, Ok(2));
+        assert!(request.starts_with("POST / "));
+        assert!(request.contains("method=track.scrobble"));
+        assert!(request.contains("timestamp%5B1%5D=1700000300"));
+        assert!(request.contains("api_sig="));
+    }
+
+    #[test]
+    fn submit_lastfm_rejects_invalid_parameters() {
+        let (url, server) = mock_server("400 Bad Request", r#"{"error":6,"message":"Invalid parameters"}"#);
+
+        let result = tauri::async_runtime::block_on(submit_lastfm(&lastfm_session(&url), &[scrobble(1, 1_700_000_000)]));
+        server.join().unwrap();
+
+        assert!(matches!(result, Err(SubmitError::Rejected(_))));
+    }
+
+    #[test]
+    fn submit_lastfm_retries_when_service_is_offline() {
+        let (url, server) = mock_server("503 Service Unavailable", r#"{"error":11,"message":"Service Offline"}"#);
+
+        let result = tauri::async_runtime::block_on(submit_lastfm(&lastfm_session(&url), &[scrobble(1, 1_700_000_000)]));
+        server.join().unwrap();
+
+        assert!(matches!(result, Err(SubmitError::Retry(_))));
+    }
+
+    fn listenbrainz_session(api_url: &str) -> ListenBrainzSession {
+        ListenBrainzSession { token: "TOKEN".into(), api_url: api_url.into() }
+    }
+
+    #[test]
+    fn submit_listenbrainz_uses_configured_url() {
+        let (url, server) = mock_server("200 OK", r#"{"status":"ok"}"#);
+        let batch = [scrobble(1, 1_700_000_000), scrobble(2, 1_700_000_300)];
+
+        let result = tauri::async_runtime::block_on(submit_listenbrainz(&listenbrainz_session(&format!("{}/", url)), &batch));
+        let request = server.join().unwrap();
+
+        assert_eq!(result, Ok(2));
+        assert!(request.starts_with("POST /1/submit-listens "));
+        assert!(request.to_lowercase().contains("authorization: token token"));
+        assert!(request.contains(r#""listen_type":"import""#));
+        assert!(request.contains(r#""listened_at":1700000300"#));
+    }
+
+    #[test]
+    fn submit_listenbrainz_rejects_bad_requests() {
+        let (url, server) = mock_server("400 Bad Request", r#"{"code":400,"error":"Invalid listen"}"#);
+
+        let result = tauri::async_runtime::block_on(submit_listenbrainz(&listenbrainz_session(&url), &[scrobble(1, 1_700_000_000)]));
+        server.join().unwrap();
+
+        assert_eq!(result, Err(SubmitError::Rejected("ListenBrainz responded with 400 Bad Request: Invalid listen".into())));
+    }
+
+    #[test]
+    fn submit_listenbrainz_retries_server_errors() {
+        let (url, server) = mock_server("503 Service Unavailable", "");
+
+        let result = tauri::async_runtime::block_on(submit_listenbrainz(&listenbrainz_session(&url), &[scrobble(1, 1_700_000_000)]));
+        server.join().unwrap();
+
+        assert!(matches!(result, Err(SubmitError::Retry(_))));
+    }
+}
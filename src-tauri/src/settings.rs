@@ -0,0 +1,103 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::artists;
+use crate::covers;
+use crate::db::get_db_connection;
+
+pub(crate) fn get_setting(conn: &Connection, key: &str) -> Result<Option<String>, rusqlite::Error> {
+    conn.query_row("SELECT value FROM setting WHERE key = ?1", params![key], |row| row.get(0))
+        .optional()
+}
+
+pub(crate) fn set_setting(conn: &Connection, key: &str, value: &str) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "INSERT INTO setting (key, value) VALUES (?1, ?2)
+        ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        params![key, value],
+    )?;
+    Ok(())
+}
+
+pub(crate) fn remove_setting(conn: &Connection, key: &str) -> Result<(), rusqlite::Error> {
+    conn.execute("DELETE FROM setting WHERE key = ?1", params![key])?;
+    Ok(())
+}
+
+pub(crate) fn get_list_setting(conn: &Connection, key: &str, default: &[&str]) -> Vec<String> {
+    get_setting(conn, key)
+        .ok()
+        .flatten()
+        .and_then(|value| serde_json::from_str::<Vec<String>>(&value).ok())
+        .unwrap_or_else(|| default.iter().map(|v| v.to_string()).collect())
+}
+
+pub(crate) fn set_list_setting(conn: &Connection, key: &str, values: &[String]) -> Result<(), rusqlite::Error> {
+    let values = values
+        .iter()
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .collect::<Vec<_>>();
+
+    set_setting(conn, key, &serde_json::to_string(&values).unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn get_cover_patterns(app: tauri::AppHandle) -> Result<String, String> {
+    let conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    let patterns = get_list_setting(&conn, covers::COVER_PATTERNS_KEY, &covers::DEFAULT_COVER_PATTERNS);
+
+    Ok(serde_json::to_string(&patterns).unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn set_cover_patterns(patterns: Vec<String>, app: tauri::AppHandle) -> Result<String, String> {
+    let conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    set_list_setting(&conn, covers::COVER_PATTERNS_KEY, &patterns).map_err(|e| e.to_string())?;
+
+    Ok("Cover patterns saved, rescan to apply them".into())
+}
+
+#[tauri::command]
+pub fn reset_cover_patterns(app: tauri::AppHandle) -> Result<String, String> {
+    let conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    remove_setting(&conn, covers::COVER_PATTERNS_KEY).map_err(|e| e.to_string())?;
+
+    Ok("Cover patterns restored, rescan to apply them".into())
+}
+
+#[tauri::command]
+pub fn get_artist_separators(app: tauri::AppHandle) -> Result<String, String> {
+    let conn = get_db_connection(app).map_err(|e| e.to_string())?;
+
+    Ok(serde_json::json!({
+        "separators": get_list_setting(&conn, artists::ARTIST_SEPARATORS_KEY, &artists::DEFAULT_ARTIST_SEPARATORS),
+        "featuring": get_list_setting(&conn, artists::FEATURING_SEPARATORS_KEY, &artists::DEFAULT_FEATURING_SEPARATORS),
+        "exceptions": get_list_setting(&conn, artists::SPLIT_EXCEPTIONS_KEY, &artists::DEFAULT_SPLIT_EXCEPTIONS),
+    })
+    .to_string())
+}
+
+#[tauri::command]
+pub fn set_artist_separators(
+    separators: Vec<String>,
+    featuring: Vec<String>,
+    exceptions: Vec<String>,
+    app: tauri::AppHandle
+) -> Result<String, String> {
+    let conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    set_list_setting(&conn, artists::ARTIST_SEPARATORS_KEY, &separators).map_err(|e| e.to_string())?;
+    set_list_setting(&conn, artists::FEATURING_SEPARATORS_KEY, &featuring).map_err(|e| e.to_string())?;
+    set_list_setting(&conn, artists::SPLIT_EXCEPTIONS_KEY, &exceptions).map_err(|e| e.to_string())?;
+
+    Ok("Artist separators saved, rescan to apply them".into())
+}
+
+#[tauri::command]
+pub fn reset_artist_separators(app: tauri::AppHandle) -> Result<String, String> {
+    let conn = get_db_connection(app).map_err(|e| e.to_string())?;
+    for key in [artists::ARTIST_SEPARATORS_KEY, artists::FEATURING_SEPARATORS_KEY, artists::SPLIT_EXCEPTIONS_KEY] {
+        remove_setting(&conn, key).map_err(|e| e.to_string())?;
+    }
+
+    Ok("Artist separators restored, rescan to apply them".into())
+}
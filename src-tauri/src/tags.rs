@@ -0,0 +1,843 @@
+use std::{
+    borrow::Cow,
+    error::Error,
+    fs::File,
+    io::{BufReader, Seek},
+    path::Path,
+    time::Duration,
+};
+
+use lofty::{
+    ape::{ApeFile, ApeItem, ApeTag},
+    config::{ParseOptions, WriteOptions},
+    file::{AudioFile, FileType, TaggedFileExt},
+    flac::FlacFile,
+    id3::v2::{
+        CommentFrame, ExtendedTextFrame, ExtendedUrlFrame, Frame, FrameId, Id3v2Tag, PopularimeterFrame,
+        TextInformationFrame, TimestampFrame, UnsynchronizedTextFrame, UrlLinkFrame,
+    },
+    iff::{aiff::AiffFile, wav::WavFile},
+    mp4::{Atom, AtomData, AtomIdent, Ilst, Mp4File},
+    mpeg::MpegFile,
+    musepack::MpcFile,
+    ogg::{tag::VorbisComments, OggPictureStorage, OpusFile, SpeexFile, VorbisFile},
+    picture::{MimeType, Picture, PictureType},
+    probe::Probe,
+    tag::{items::Timestamp, Accessor, ItemKey, ItemValue, Tag, TagExt},
+    wavpack::WavPackFile,
+    TextEncoding,
+};
+
+pub(crate) enum NativeTag {
+    Id3v2(Id3v2Tag),
+    VorbisComments(VorbisComments),
+    Ilst(Ilst),
+    Ape(ApeTag),
+}
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct MusicBrainzIds {
+    pub recording: Option<String>,
+    pub track: Option<String>,
+    pub release: Option<String>,
+    pub release_group: Option<String>,
+    pub artists: Vec<String>,
+    pub release_artists: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ArtistTags {
+    pub artists: Vec<String>,
+    pub album_artists: Vec<String>,
+    pub remixers: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ExtendedTags {
+    pub composer: Option<String>,
+    pub conductor: Option<String>,
+    pub lyricist: Option<String>,
+    pub bpm: Option<u32>,
+    pub initial_key: Option<String>,
+    pub mood: Option<String>,
+    pub comment: Option<String>,
+    pub label: Option<String>,
+    pub catalog_number: Option<String>,
+    pub isrc: Option<String>,
+    pub barcode: Option<String>,
+    pub original_year: Option<i32>,
+    pub release_date: Option<String>,
+    pub title_sort: Option<String>,
+    pub artist_sort: Option<String>,
+    pub album_sort: Option<String>,
+    pub album_artist_sort: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub(crate) struct TagField {
+    pub key: String,
+    pub values: Vec<String>,
+    #[serde(default)]
+    pub editable: bool,
+}
+
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TagPatch {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album_title: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub year: Option<u16>,
+    pub genre: Option<String>,
+    #[serde(default)]
+    pub fields: Vec<TagField>,
+}
+
+fn apply_common(tag: &mut impl Accessor, patch: &TagPatch) {
+    match patch.title.as_deref() {
+        Some("") => tag.remove_title(),
+        Some(title) => tag.set_title(title.to_string()),
+        None => {}
+    }
+    match patch.artist.as_deref() {
+        Some("") => tag.remove_artist(),
+        Some(artist) => tag.set_artist(artist.to_string()),
+        None => {}
+    }
+    match patch.album_title.as_deref() {
+        Some("") => tag.remove_album(),
+        Some(album_title) => tag.set_album(album_title.to_string()),
+        None => {}
+    }
+    match patch.track_number {
+        Some(0) => tag.remove_track(),
+        Some(track_number) => tag.set_track(track_number),
+        None => {}
+    }
+    match patch.disc_number {
+        Some(0) => tag.remove_disk(),
+        Some(disc_number) => tag.set_disk(disc_number),
+        None => {}
+    }
+    match patch.year {
+        Some(0) => tag.remove_date(),
+        Some(year) => tag.set_date(Timestamp { year, ..Default::default() }),
+        None => {}
+    }
+    match patch.genre.as_deref() {
+        Some("") => tag.remove_genre(),
+        Some(genre) => tag.set_genre(genre.to_string()),
+        None => {}
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct TagChanges {
+    pub previous: Vec<TagField>,
+    pub new: Vec<TagField>,
+    pub pictures: bool,
+}
+
+impl TagChanges {
+    pub fn is_empty(&self) -> bool {
+        self.previous.is_empty() && !self.pictures
+    }
+}
+
+pub(crate) fn changed_fields(before: &[TagField], after: &[TagField]) -> (Vec<TagField>, Vec<TagField>) {
+    let values = |fields: &[TagField], key: &str| fields.iter().find(|field| field.key == key).map(|field| field.values.clone());
+    let mut previous: Vec<TagField> = Vec::new();
+    let mut new = Vec::new();
+
+    for field in before.iter().chain(after).filter(|field| field.editable) {
+        if previous.iter().any(|changed| changed.key == field.key) {
+            continue;
+        }
+        let before_values = values(before, &field.key).unwrap_or_default();
+        let after_values = values(after, &field.key).unwrap_or_default();
+        if before_values != after_values {
+            previous.push(TagField { key: field.key.clone(), values: before_values, editable: true });
+            new.push(TagField { key: field.key.clone(), values: after_values, editable: true });
+        }
+    }
+
+    (previous, new)
+}
+
+const TIMESTAMP_FRAMES: [&str; 5] = ["TDRC", "TDOR", "TDRL", "TDEN", "TDTG"];
+
+fn push_field(fields: &mut Vec<TagField>, key: String, values: Vec<String>, editable: bool) {
+    match fields.iter_mut().find(|field| field.key == key) {
+        Some(field) => {
+            field.values.extend(values);
+            field.editable &= editable;
+        }
+        None => fields.push(TagField { key, values, editable }),
+    }
+}
+
+// MP4 atom names are Latin-1, e.g. the first byte of `©nam` is 0xA9
+fn atom_key(ident: &AtomIdent) -> String {
+    match ident {
+        AtomIdent::Fourcc(fourcc) => fourcc.iter().map(|byte| *byte as char).collect(),
+        AtomIdent::Freeform { mean, name } => format!("----:{}:{}", mean, name),
+    }
+}
+
+fn atom_ident(key: &str) -> Result<AtomIdent<'static>, Box<dyn Error>> {
+    if let Some(freeform) = key.strip_prefix("----:") {
+        let (mean, name) = freeform.split_once(':').ok_or("Freeform atoms are written as ----:mean:name")?;
+        return Ok(AtomIdent::Freeform { mean: Cow::Owned(mean.into()), name: Cow::Owned(name.into()) });
+    }
+
+    let bytes = key.chars().map(|c| u8::try_from(c as u32)).collect::<Result<Vec<_>, _>>();
+    match bytes.ok().and_then(|bytes| <[u8; 4]>::try_from(bytes).ok()) {
+        Some(fourcc) => Ok(AtomIdent::Fourcc(fourcc)),
+        None => Err(format!("{} is not a valid MP4 atom", key).into()),
+    }
+}
+
+const POPM_EMAIL: &str = "Windows Media Player 9 Series";
+const PICARD_EMAIL: &str = "users@musicbrainz.org";
+
+// POPM byte values for 0.5 to 5 stars, as used by MusicBee and MediaMonkey. Whole stars match Windows Media Player
+const POPM_SCALE: [u8; 10] = [13, 1, 54, 64, 118, 128, 186, 196, 242, 255];
+
+// Ratings are kept in half stars (0-10) everywhere except the frontend, which shows 0-5
+pub(crate) fn popm_to_half_stars(email: &str, value: u8) -> Option<u8> {
+    if value == 0 {
+        return None;
+    }
+
+    if email == PICARD_EMAIL {
+        return Some(((value as f32 / 25.5).round() as u8).clamp(1, 10));
+    }
+
+    POPM_SCALE
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, scale)| scale.abs_diff(value))
+        .map(|(i, _)| i as u8 + 1)
+}
+
+fn half_stars_to_popm(rating: u8) -> u8 {
+    match rating {
+        0 => 0,
+        r => POPM_SCALE[(r.min(10) - 1) as usize],
+    }
+}
+
+// Text ratings are written as 0-100. Players also use 1-5, so small values are read as whole stars
+fn text_to_half_stars(value: &str) -> Option<u8> {
+    let value = value.trim().parse::<f32>().ok()?;
+
+    let half_stars = if value <= 5.0 {
+        value * 2.0
+    } else {
+        value.min(100.0) / 10.0
+    };
+
+    Some(half_stars.round() as u8).filter(|r| *r > 0)
+}
+
+fn half_stars_to_text(rating: u8) -> String {
+    (rating.min(10) as u32 * 10).to_string()
+}
+
+fn is_mbid(value: &str) -> bool {
+    let groups = value.split('-').map(str::len).collect::<Vec<_>>();
+    groups == [8, 4, 4, 4, 12] && value.chars().all(|c| c == '-' || c.is_ascii_hexdigit())
+}
+
+fn guess_file_type(file: &mut File) -> Result<FileType, Box<dyn Error>> {
+    let file_type = Probe::new(BufReader::new(&mut *file))
+        .guess_file_type()?
+        .file_type()
+        .ok_or("Unsupported file type")?;
+    file.rewind()?;
+
+    Ok(file_type)
+}
+
+pub(crate) struct FileInfo {
+    pub container: &'static str,
+    pub codec: Option<&'static str>,
+    pub duration: Duration,
+    pub sample_rate: Option<u32>,
+    pub bit_depth: Option<u8>,
+    pub channels: Option<u8>,
+    // In kbps
+    pub bitrate: Option<u32>,
+}
+
+pub(crate) fn read_file_info(path: &Path) -> Result<FileInfo, Box<dyn Error>> {
+    let file = Probe::open(path)?
+        .options(ParseOptions::new().read_tags(false).read_cover_art(false))
+        .guess_file_type()?
+        .read()?;
+
+    let (container, codec) = match file.file_type() {
+        FileType::Aac => ("ADTS", Some("AAC")),
+        FileType::Aiff => ("AIFF", None),
+        FileType::Ape => ("APE", Some("APE")),
+        FileType::Flac => ("FLAC", Some("FLAC")),
+        FileType::Mpeg => ("MPEG", None),
+        FileType::Mp4 => ("MP4", None),
+        FileType::Mpc => ("Musepack", Some("Musepack")),
+        FileType::Opus => ("Ogg", Some("Opus")),
+        FileType::Vorbis => ("Ogg", Some("Vorbis")),
+        FileType::Speex => ("Ogg", Some("Speex")),
+        FileType::Wav => ("WAV", None),
+        FileType::WavPack => ("WavPack", Some("WavPack")),
+        _ => return Err("Unsupported file type".into()),
+    };
+
+    let properties = file.properties();
+    Ok(FileInfo {
+        container,
+        codec,
+        duration: properties.duration(),
+        sample_rate: properties.sample_rate().filter(|rate| *rate > 0),
+        bit_depth: properties.bit_depth().filter(|depth| *depth > 0),
+        channels: properties.channels().filter(|channels| *channels > 0),
+        bitrate: properties.audio_bitrate().filter(|bitrate| *bitrate > 0),
+    })
+}
+
+pub(crate) fn read_tag(path: &Path) -> Result<Tag, Box<dyn Error>> {
+    let file = Probe::open(path)?
+        .options(ParseOptions::new().read_properties(false).read_cover_art(false))
+        .guess_file_type()?
+        .read()?;
+
+    Ok(file
+        .primary_tag()
+        .or(file.first_tag())
+        .cloned()
+        .unwrap_or_else(|| Tag::new(file.primary_tag_type())))
+}
+
+pub(crate) fn read_native_tag(path: &Path) -> Result<NativeTag, Box<dyn Error>> {
+    let mut file = File::open(path)?;
+    let file_type = guess_file_type(&mut file)?;
+    let options = ParseOptions::new().read_properties(false);
+
+    let tag = match file_type {
+        FileType::Mpeg => NativeTag::Id3v2(MpegFile::read_from(&mut file, options)?.id3v2().cloned().unwrap_or_default()),
+        FileType::Aiff => NativeTag::Id3v2(AiffFile::read_from(&mut file, options)?.id3v2().cloned().unwrap_or_default()),
+        FileType::Wav => NativeTag::Id3v2(WavFile::read_from(&mut file, options)?.id3v2().cloned().unwrap_or_default()),
+        FileType::Flac => {
+            let flac = FlacFile::read_from(&mut file, options)?;
+            let mut tag = flac.vorbis_comments().cloned().unwrap_or_default();
+            // FLAC keeps pictures in their own blocks, which are rewritten from the tag on save
+            for (picture, info) in flac.pictures() {
+                tag.insert_picture(picture.clone(), Some(*info))?;
+            }
+            NativeTag::VorbisComments(tag)
+        }
+        FileType::Opus => NativeTag::VorbisComments(OpusFile::read_from(&mut file, options)?.vorbis_comments().clone()),
+        FileType::Vorbis => NativeTag::VorbisComments(VorbisFile::read_from(&mut file, options)?.vorbis_comments().clone()),
+        FileType::Speex => NativeTag::VorbisComments(SpeexFile::read_from(&mut file, options)?.vorbis_comments().clone()),
+        FileType::Mp4 => NativeTag::Ilst(Mp4File::read_from(&mut file, options)?.ilst().cloned().unwrap_or_default()),
+        FileType::Ape => NativeTag::Ape(ApeFile::read_from(&mut file, options)?.ape().cloned().unwrap_or_default()),
+        FileType::WavPack => NativeTag::Ape(WavPackFile::read_from(&mut file, options)?.ape().cloned().unwrap_or_default()),
+        FileType::Mpc => NativeTag::Ape(MpcFile::read_from(&mut file, options)?.ape().cloned().unwrap_or_default()),
+        other => return Err(format!("Tags are not supported for {:?} files", other).into()),
+    };
+
+    Ok(tag)
+}
+
+pub(crate) fn save_native_tag(path: &Path, tag: &NativeTag) -> Result<(), Box<dyn Error>> {
+    let options = WriteOptions::default();
+
+    match tag {
+        NativeTag::Id3v2(tag) => tag.save_to_path(path, options)?,
+        NativeTag::VorbisComments(tag) => tag.save_to_path(path, options)?,
+        NativeTag::Ilst(tag) => tag.save_to_path(path, options)?,
+        NativeTag::Ape(tag) => tag.save_to_path(path, options)?,
+    }
+
+    Ok(())
+}
+
+const POPM: FrameId<'static> = FrameId::Valid(Cow::Borrowed("POPM"));
+
+impl NativeTag {
+    pub fn rating(&self) -> Option<u8> {
+        match self {
+            NativeTag::Id3v2(tag) => tag.into_iter().find_map(|frame| match frame {
+                Frame::Popularimeter(popm) => popm_to_half_stars(&popm.email, popm.rating),
+                _ => None,
+            }),
+            NativeTag::VorbisComments(tag) => tag.get("RATING").and_then(text_to_half_stars),
+            NativeTag::Ilst(tag) => tag.get(&AtomIdent::Fourcc(*b"rate")).and_then(|atom| {
+                atom.data().find_map(|data| match data {
+                    AtomData::UTF8(text) | AtomData::UTF16(text) => text_to_half_stars(text),
+                    AtomData::UnsignedInteger(value) => text_to_half_stars(&value.to_string()),
+                    AtomData::SignedInteger(value) => text_to_half_stars(&value.to_string()),
+                    _ => None,
+                })
+            }),
+            NativeTag::Ape(tag) => tag.get("Rating").and_then(|item| match item.value() {
+                ItemValue::Text(text) => text_to_half_stars(text),
+                _ => None,
+            }),
+        }
+    }
+
+    fn to_generic(&self) -> Tag {
+        match self {
+            NativeTag::Id3v2(tag) => tag.clone().into(),
+            NativeTag::VorbisComments(tag) => tag.clone().into(),
+            NativeTag::Ilst(tag) => tag.clone().into(),
+            NativeTag::Ape(tag) => tag.clone().into(),
+        }
+    }
+
+    pub fn musicbrainz_ids(&self) -> MusicBrainzIds {
+        let tag = self.to_generic();
+        let id = |key: ItemKey| tag.get_string(key).map(str::trim).filter(|id| is_mbid(id)).map(String::from);
+
+        // Multiple artist IDs are either separate items or joined into one
+        let ids = |key: ItemKey| {
+            tag.get_strings(key)
+                .flat_map(|ids| ids.split(['/', ';', ',', '\0']))
+                .map(str::trim)
+                .filter(|id| is_mbid(id))
+                .map(String::from)
+                .collect()
+        };
+
+        MusicBrainzIds {
+            recording: id(ItemKey::MusicBrainzRecordingId),
+            track: id(ItemKey::MusicBrainzTrackId),
+            release: id(ItemKey::MusicBrainzReleaseId),
+            release_group: id(ItemKey::MusicBrainzReleaseGroupId),
+            artists: ids(ItemKey::MusicBrainzArtistId),
+            release_artists: ids(ItemKey::MusicBrainzReleaseArtistId),
+        }
+    }
+
+    pub fn artist_tags(&self) -> ArtistTags {
+        let tag = self.to_generic();
+        let values = |key: ItemKey| {
+            tag.get_strings(key)
+                .flat_map(|value| value.split('\0'))
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        // The plural fields are explicit lists, so they're preferred when present
+        let either = |list: ItemKey, single: ItemKey| {
+            let list = values(list);
+            if list.is_empty() { values(single) } else { list }
+        };
+
+        ArtistTags {
+            artists: either(ItemKey::TrackArtists, ItemKey::TrackArtist),
+            album_artists: either(ItemKey::AlbumArtists, ItemKey::AlbumArtist),
+            remixers: values(ItemKey::Remixer),
+        }
+    }
+
+    pub fn extended_tags(&self) -> ExtendedTags {
+        let tag = self.to_generic();
+        let text = |key: ItemKey| {
+            let values = tag
+                .get_strings(key)
+                .flat_map(|value| value.split('\0'))
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .collect::<Vec<_>>();
+            Some(values.join("; ")).filter(|value| !value.is_empty())
+        };
+
+        ExtendedTags {
+            composer: text(ItemKey::Composer),
+            conductor: text(ItemKey::Conductor),
+            lyricist: text(ItemKey::Lyricist),
+            // Decimal BPMs are written by some taggers, e.g. "128.00"
+            bpm: text(ItemKey::IntegerBpm)
+                .or(text(ItemKey::Bpm))
+                .and_then(|bpm| bpm.parse::<f64>().ok())
+                .map(|bpm| bpm.round() as u32)
+                .filter(|bpm| *bpm > 0),
+            initial_key: text(ItemKey::InitialKey),
+            mood: text(ItemKey::Mood),
+            comment: text(ItemKey::Comment),
+            // ID3v2 has no label frame, TPUB is used instead
+            label: text(ItemKey::Label).or(text(ItemKey::Publisher)),
+            catalog_number: text(ItemKey::CatalogNumber),
+            isrc: text(ItemKey::Isrc),
+            barcode: text(ItemKey::Barcode),
+            // Either a year or a full date such as 1977-10-28
+            original_year: text(ItemKey::OriginalReleaseDate).and_then(|date| date.get(0..4)?.parse().ok()),
+            release_date: text(ItemKey::ReleaseDate),
+            title_sort: text(ItemKey::TrackTitleSortOrder),
+            artist_sort: text(ItemKey::TrackArtistSortOrder),
+            album_sort: text(ItemKey::AlbumTitleSortOrder),
+            album_artist_sort: text(ItemKey::AlbumArtistSortOrder),
+        }
+    }
+
+    pub fn disc_subtitle(&self) -> Option<String> {
+        self.to_generic()
+            .get_string(ItemKey::SetSubtitle)
+            .map(str::trim)
+            .filter(|subtitle| !subtitle.is_empty())
+            .map(String::from)
+    }
+
+    pub fn is_compilation(&self) -> bool {
+        self.to_generic()
+            .get_string(ItemKey::FlagCompilation)
+            .is_some_and(|flag| matches!(flag.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+    }
+
+    pub fn format_name(&self) -> &'static str {
+        match self {
+            NativeTag::Id3v2(_) => "ID3v2",
+            NativeTag::VorbisComments(_) => "Vorbis Comments",
+            NativeTag::Ilst(_) => "MP4",
+            NativeTag::Ape(_) => "APE",
+        }
+    }
+
+    pub fn fields(&self) -> Vec<TagField> {
+        let mut fields = Vec::new();
+        let split = |text: &str| text.split('\0').map(String::from).collect::<Vec<_>>();
+
+        match self {
+            NativeTag::Id3v2(tag) => {
+                for frame in tag.iter() {
+                    let id = frame.id().as_str();
+                    let (key, values) = match frame {
+                        Frame::Text(frame) => (id.to_string(), split(&frame.value)),
+                        Frame::UserText(frame) => (format!("TXXX:{}", frame.description), split(&frame.content)),
+                        Frame::Url(frame) => (id.to_string(), vec![frame.url().to_string()]),
+                        Frame::UserUrl(frame) => (format!("WXXX:{}", frame.description), vec![frame.content.to_string()]),
+                        Frame::Comment(frame) => (format!("COMM:{}", frame.description), vec![frame.content.to_string()]),
+                        Frame::UnsynchronizedText(frame) => (format!("USLT:{}", frame.description), vec![frame.content.to_string()]),
+                        Frame::Timestamp(frame) => (id.to_string(), vec![frame.timestamp.to_string()]),
+                        _ => {
+                            push_field(&mut fields, id.to_string(), Vec::new(), false);
+                            continue;
+                        }
+                    };
+                    push_field(&mut fields, key, values, true);
+                }
+            }
+            NativeTag::VorbisComments(tag) => {
+                for (key, value) in tag.items() {
+                    push_field(&mut fields, key.to_uppercase(), vec![value.to_string()], true);
+                }
+            }
+            NativeTag::Ilst(tag) => {
+                for atom in tag {
+                    let pair = match atom.ident() {
+                        AtomIdent::Fourcc([b't', b'r', b'k', b'n']) => Some((tag.track(), tag.track_total())),
+                        AtomIdent::Fourcc([b'd', b'i', b's', b'k']) => Some((tag.disk(), tag.disk_total())),
+                        _ => None,
+                    };
+                    if let Some((number, total)) = pair {
+                        let value = match total.filter(|total| *total > 0) {
+                            Some(total) => format!("{}/{}", number.unwrap_or(0), total),
+                            None => number.unwrap_or(0).to_string(),
+                        };
+                        push_field(&mut fields, atom_key(atom.ident()), vec![value], true);
+                        continue;
+                    }
+
+                    let values = atom
+                        .data()
+                        .map(|data| match data {
+                            AtomData::UTF8(text) | AtomData::UTF16(text) => Some(text.clone()),
+                            AtomData::SignedInteger(number) => Some(number.to_string()),
+                            AtomData::UnsignedInteger(number) => Some(number.to_string()),
+                            AtomData::Bool(flag) => Some(u8::from(*flag).to_string()),
+                            _ => None,
+                        })
+                        .collect::<Option<Vec<_>>>();
+                    let editable = values.is_some();
+                    push_field(&mut fields, atom_key(atom.ident()), values.unwrap_or_default(), editable);
+                }
+            }
+            NativeTag::Ape(tag) => {
+                for item in tag {
+                    match item.value() {
+                        ItemValue::Text(text) | ItemValue::Locator(text) => push_field(&mut fields, item.key().to_string(), split(text), true),
+                        ItemValue::Binary(_) => push_field(&mut fields, item.key().to_string(), Vec::new(), false),
+                    }
+                }
+            }
+        }
+
+        fields
+    }
+
+    pub fn set_field(&mut self, key: &str, values: &[String]) -> Result<(), Box<dyn Error>> {
+        let key = key.trim();
+        if key.is_empty() {
+            return Err("Field names can't be empty".into());
+        }
+
+        match self {
+            NativeTag::Id3v2(tag) => {
+                let joined = values.join("\0");
+                if let Some(description) = key.strip_prefix("TXXX:") {
+                    tag.remove_user_text(description);
+                    if !values.is_empty() {
+                        tag.insert(Frame::UserText(ExtendedTextFrame::new(TextEncoding::UTF8, description.to_string(), joined)));
+                    }
+                } else if let Some(description) = key.strip_prefix("WXXX:") {
+                    tag.retain(|frame| !matches!(frame, Frame::UserUrl(frame) if frame.description == description));
+                    if let Some(url) = values.first() {
+                        tag.insert(Frame::UserUrl(ExtendedUrlFrame::new(TextEncoding::UTF8, description.to_string(), url.clone())));
+                    }
+                } else if let Some(description) = key.strip_prefix("COMM:") {
+                    tag.retain(|frame| !matches!(frame, Frame::Comment(frame) if frame.description == description));
+                    if !values.is_empty() {
+                        let frame = CommentFrame::new(TextEncoding::UTF8, *b"eng", description.to_string(), values.join("\n"));
+                        tag.insert(Frame::Comment(frame));
+                    }
+                } else if let Some(description) = key.strip_prefix("USLT:") {
+                    tag.retain(|frame| !matches!(frame, Frame::UnsynchronizedText(frame) if frame.description == description));
+                    if !values.is_empty() {
+                        let frame = UnsynchronizedTextFrame::new(TextEncoding::UTF8, *b"eng", description.to_string(), values.join("\n"));
+                        tag.insert(Frame::UnsynchronizedText(frame));
+                    }
+                } else {
+                    let id = FrameId::new(key.to_string()).map_err(|_| format!("{} is not a valid ID3v2 frame", key))?;
+                    let _ = tag.remove(&id);
+                    if values.is_empty() {
+                        return Ok(());
+                    }
+
+                    let frame = if TIMESTAMP_FRAMES.contains(&key) {
+                        let timestamp = values[0].parse::<Timestamp>().map_err(|_| format!("{} is not a valid date", values[0]))?;
+                        Frame::Timestamp(TimestampFrame::new(id, TextEncoding::UTF8, timestamp))
+                    } else if key.starts_with('T') {
+                        Frame::Text(TextInformationFrame::new(id, TextEncoding::UTF8, joined))
+                    } else if key.starts_with('W') {
+                        Frame::Url(UrlLinkFrame::new(id, values[0].clone()))
+                    } else {
+                        return Err(format!("{} can't be edited as text", key).into());
+                    };
+                    tag.insert(frame);
+                }
+            }
+            NativeTag::VorbisComments(tag) => {
+                // Field names are printable ASCII other than '='
+                if !key.chars().all(|c| (' '..='}').contains(&c) && c != '=') {
+                    return Err(format!("{} is not a valid Vorbis comment field", key).into());
+                }
+                let _ = tag.remove(key);
+                for value in values {
+                    tag.push(key.to_uppercase(), value.clone());
+                }
+            }
+            NativeTag::Ilst(tag) => {
+                let ident = atom_ident(key)?;
+                // Track and disc numbers are a binary number and total pair, written as `number/total`
+                if key == "trkn" || key == "disk" {
+                    let _ = tag.remove(&ident);
+                    let Some(value) = values.first() else { return Ok(()) };
+                    let (number, total) = value.split_once('/').unwrap_or((value, ""));
+                    let parse = |text: &str| text.trim().parse::<u32>().map_err(|_| format!("{} expects a number", key));
+                    let number = parse(number)?;
+                    let total = Some(total).filter(|total| !total.trim().is_empty()).map(parse).transpose()?;
+                    if key == "trkn" {
+                        tag.set_track(number);
+                        if let Some(total) = total {
+                            tag.set_track_total(total);
+                        }
+                    } else {
+                        tag.set_disk(number);
+                        if let Some(total) = total {
+                            tag.set_disk_total(total);
+                        }
+                    }
+                    return Ok(());
+                }
+                let existing = tag.get(&ident).and_then(|atom| atom.data().next().cloned());
+                if !values.is_empty() && matches!(existing, Some(AtomData::Picture(_) | AtomData::Unknown { .. })) {
+                    return Err(format!("{} can't be edited as text", key).into());
+                }
+                let _ = tag.remove(&ident);
+
+                // Numeric atoms such as tmpo and flags such as cpil keep their type
+                let data = values
+                    .iter()
+                    .map(|value| match (&existing, value.trim().parse::<i32>()) {
+                        (Some(AtomData::SignedInteger(_) | AtomData::UnsignedInteger(_)), Ok(number)) => Ok(AtomData::SignedInteger(number)),
+                        (Some(AtomData::Bool(_)), Ok(number)) => Ok(AtomData::Bool(number != 0)),
+                        (Some(AtomData::SignedInteger(_) | AtomData::UnsignedInteger(_) | AtomData::Bool(_)), Err(_)) => {
+                            Err(format!("{} expects a number", key))
+                        }
+                        _ => Ok(AtomData::UTF8(value.clone())),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if let Some(atom) = Atom::from_collection(ident, data) {
+                    tag.insert(atom);
+                }
+            }
+            NativeTag::Ape(tag) => {
+                if tag.get(key).is_some_and(|item| matches!(item.value(), ItemValue::Binary(_))) && !values.is_empty() {
+                    return Err(format!("{} can't be edited as text", key).into());
+                }
+                tag.remove(key);
+                if !values.is_empty() {
+                    tag.insert(ApeItem::new(key.to_string(), ItemValue::Text(values.join("\0")))?);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn apply_patch(&mut self, patch: &TagPatch) -> Result<(), Box<dyn Error>> {
+        let album_artist_key = match self {
+            NativeTag::Id3v2(tag) => {
+                apply_common(tag, patch);
+                "TPE2"
+            }
+            NativeTag::VorbisComments(tag) => {
+                apply_common(tag, patch);
+                "ALBUMARTIST"
+            }
+            NativeTag::Ilst(tag) => {
+                apply_common(tag, patch);
+                "aART"
+            }
+            NativeTag::Ape(tag) => {
+                apply_common(tag, patch);
+                "Album Artist"
+            }
+        };
+        match patch.album_artist.as_deref() {
+            Some("") => self.set_field(album_artist_key, &[])?,
+            Some(album_artist) => self.set_field(album_artist_key, &[album_artist.to_string()])?,
+            None => {}
+        }
+
+        for field in &patch.fields {
+            let values = field.values.iter().filter(|value| !value.is_empty()).cloned().collect::<Vec<_>>();
+            self.set_field(&field.key, &values).map_err(|e| format!("{}: {}", field.key, e))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn set_rating(&mut self, rating: u8) -> Result<(), Box<dyn Error>> {
+        match self {
+            NativeTag::Id3v2(tag) => {
+                // Other players keep their own POPM frame, update all of them so they agree.
+                // Existing frames keep their owner and play counter
+                let mut frames = tag
+                    .remove(&POPM)
+                    .filter_map(|frame| match frame {
+                        Frame::Popularimeter(popm) => Some(popm),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                if rating == 0 {
+                    return Ok(());
+                }
+                if frames.is_empty() {
+                    frames.push(PopularimeterFrame::new(POPM_EMAIL, 0, 0));
+                }
+
+                for mut popm in frames {
+                    popm.rating = if popm.email == PICARD_EMAIL {
+                        (rating.min(10) as f32 * 25.5).round() as u8
+                    } else {
+                        half_stars_to_popm(rating)
+                    };
+                    tag.insert(Frame::Popularimeter(popm));
+                }
+            }
+            NativeTag::VorbisComments(tag) => {
+                let _ = tag.remove("RATING");
+                if rating > 0 {
+                    tag.insert(String::from("RATING"), half_stars_to_text(rating));
+                }
+            }
+            NativeTag::Ilst(tag) => {
+                let ident = AtomIdent::Fourcc(*b"rate");
+                let _ = tag.remove(&ident);
+                if rating > 0 {
+                    tag.insert(Atom::new(ident, AtomData::UTF8(half_stars_to_text(rating))));
+                }
+            }
+            NativeTag::Ape(tag) => {
+                tag.remove("Rating");
+                if rating > 0 {
+                    tag.insert(ApeItem::new(String::from("Rating"), ItemValue::Text(half_stars_to_text(rating)))?);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn set_front_cover(&mut self, data: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        let mime_type = match data.as_slice() {
+            [0x89, b'P', b'N', b'G', ..] => MimeType::Png,
+            _ => MimeType::Jpeg,
+        };
+        let picture = Picture::unchecked(data)
+            .pic_type(PictureType::CoverFront)
+            .mime_type(mime_type)
+            .build();
+
+        match self {
+            NativeTag::Id3v2(tag) => {
+                tag.remove_picture_type(PictureType::CoverFront);
+                tag.insert_picture(picture);
+            }
+            NativeTag::VorbisComments(tag) => {
+                tag.remove_picture_type(PictureType::CoverFront);
+                tag.insert_picture(picture, None)?;
+            }
+            NativeTag::Ilst(tag) => {
+                tag.remove_pictures();
+                tag.insert_picture(picture);
+            }
+            NativeTag::Ape(tag) => {
+                let key = PictureType::CoverFront.as_ape_key().ok_or("Unsupported picture type")?;
+                tag.insert(ApeItem::new(key.to_string(), ItemValue::Binary(picture.as_ape_bytes()))?);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) fn read_musicbrainz_ids(path: &Path) -> Result<MusicBrainzIds, Box<dyn Error>> {
+    Ok(read_native_tag(path)?.musicbrainz_ids())
+}
+
+pub(crate) fn write_rating(path: &Path, rating: u8) -> Result<(), Box<dyn Error>> {
+    let mut tag = read_native_tag(path)?;
+    tag.set_rating(rating)?;
+    save_native_tag(path, &tag)
+}
+
+pub(crate) fn prepare_patch(path: &Path, patch: &TagPatch, cover: Option<&[u8]>) -> Result<(NativeTag, TagChanges), Box<dyn Error>> {
+    let mut tag = read_native_tag(path)?;
+    let before = tag.fields();
+    let pictures = tag.to_generic().pictures().to_vec();
+    tag.apply_patch(patch)?;
+    if let Some(data) = cover {
+        tag.set_front_cover(data.to_vec())?;
+    }
+
+    let (previous, new) = changed_fields(&before, &tag.fields());
+    let pictures = tag.to_generic().pictures() != pictures.as_slice();
+    Ok((tag, TagChanges { previous, new, pictures }))
+}
@@ -1,6 +1,6 @@
 import { get, writable } from 'svelte/store';
 import { invoke } from '@tauri-apps/api/core';
-import { getSession, lastFm, lastFmConnected } from './lastfmAPI';
+import { connectScrobbler, getSession, lastFm, lastFmConnected } from './lastfmAPI';
 import { addToast } from './notifications';
 
 export const currentSong = writable({
@@ -28,6 +28,7 @@ export async function play(song) {
             currentSong.set(song);
             startedPlayingAt.set(Math.floor(Date.now() / 1000));
             if (get(lastFmConnected)) {
+                connectScrobbler();
                 let session = await getSession();
                 lastFm.track.updateNowPlaying({
                     artist: song.artist,
@@ -35,7 +36,7 @@ export async function play(song) {
                     album: song.album_title,
                     albumArtist: song.album_artist,
                     trackNumber: song.track_number,
-                    duration: song.duration,
+                    duration: Math.round(song.duration / 1000),
                 }, session.key)
                 .then(res => console.log(res));
             };
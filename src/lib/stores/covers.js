@@ -0,0 +1,27 @@
+import { convertFileSrc, invoke } from "@tauri-apps/api/core";
+
+const thumbnails = new Map();
+
+// Full size covers can be huge scans, so views ask for a thumbnail close to the size they display.
+// format is 'jpeg' or 'webp', by default covers with transparency get a WebP
+export function getThumbnail(path, size, format = null) {
+    let key = `${size}:${format}:${path}`;
+
+    if (!thumbnails.has(key)) {
+        let thumbnail = invoke('get_cover_thumbnail', { coverPath: path, size, format })
+            .then(thumbnailPath => convertFileSrc(thumbnailPath))
+            .catch(err => {
+                console.error(err);
+                thumbnails.delete(key);
+                return convertFileSrc(path);
+            });
+        thumbnails.set(key, thumbnail);
+    }
+
+    return thumbnails.get(key);
+}
+
+// Covers can be replaced on disk by a rescan or an edit, the backend regenerates outdated thumbnails
+export function clearThumbnails() {
+    thumbnails.clear();
+}
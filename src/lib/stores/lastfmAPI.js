@@ -45,6 +45,25 @@ export async function getSession() {
     return { name, key, subscriber };
 }
 
+let scrobblerConfigured = false;
+
+// Scrobbles are queued and submitted by the backend, it only needs the session key
+export async function connectScrobbler() {
+    if (scrobblerConfigured) return;
+
+    let session = await getSession();
+    if (!session) return;
+
+    await invoke('configure_lastfm', {
+        apiKey: import.meta.env.VITE_LASTFM_API_KEY,
+        apiSecret: import.meta.env.VITE_LASTFM_API_SECRET,
+        sessionKey: session.key,
+        apiUrl: import.meta.env.VITE_LASTFM_API_URL
+    })
+        .then(() => scrobblerConfigured = true)
+        .catch(err => console.error(err));
+}
+
 export async function getArtistInfo(artist) {
     let cachedInfo = localStorage.getItem(artist.name);
 
@@ -78,6 +97,8 @@ export async function setNewPortrait(name, url) {
     let artist = JSON.parse(localStorage.getItem(name));
     let finalPath = await downloadPortrait(url);
     localStorage.setItem(name, JSON.stringify({ ...artist, thumbnail: finalPath }));
+
+    return finalPath;
 }
 
 export async function getArtistImages(artist) {
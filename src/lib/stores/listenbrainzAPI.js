@@ -0,0 +1,31 @@
+import { invoke } from "@tauri-apps/api/core";
+import { writable } from "svelte/store";
+import { deleteRecord, getRecord, insertRecord } from "./stronghold";
+
+export const listenBrainzConnected = writable(false);
+
+// The token only lives in Stronghold, the backend gets a copy to submit listens with
+export async function restoreListenBrainz() {
+    let token = await getRecord("listenbrainz_token");
+    if (!token) return;
+
+    await invoke('configure_listenbrainz', {
+        token,
+        apiUrl: import.meta.env.VITE_LISTENBRAINZ_API_URL
+    })
+        .then(() => listenBrainzConnected.set(true))
+        .catch(err => console.error(err));
+}
+
+export async function connectListenBrainz(token) {
+    if (!token) return;
+
+    await insertRecord("listenbrainz_token", token.trim());
+    await restoreListenBrainz();
+}
+
+export async function disconnectListenBrainz() {
+    await deleteRecord("listenbrainz_token");
+    await invoke('disconnect_listenbrainz');
+    listenBrainzConnected.set(false);
+}
@@ -0,0 +1,67 @@
+import { invoke } from "@tauri-apps/api/core";
+import { writable } from "svelte/store";
+import { play, setQueue } from "./audioPlayer";
+
+export const playlists = writable([]);
+
+export async function refreshPlaylists() {
+    await invoke('get_all_playlists').then(playlistsJSON => {
+        playlists.set(JSON.parse(playlistsJSON));
+    });
+}
+
+export async function loadPlaylistSongs(id) {
+    return await invoke('get_playlist_songs', { id })
+        .then(songsJSON => {
+            return JSON.parse(songsJSON);
+        });
+}
+
+export async function createPlaylist(name, filePaths = []) {
+    let id = Number(await invoke('create_playlist', { name }));
+    if (filePaths.length > 0) {
+        await invoke('add_to_playlist', { id, filePaths });
+    }
+    await refreshPlaylists();
+    return id;
+}
+
+export async function renamePlaylist(id, name) {
+    await invoke('rename_playlist', { id, name });
+    await refreshPlaylists();
+}
+
+export async function deletePlaylist(id) {
+    await invoke('delete_playlist', { id });
+    await refreshPlaylists();
+}
+
+export async function duplicatePlaylist(id, name = null) {
+    let newId = Number(await invoke('duplicate_playlist', { id, name }));
+    await refreshPlaylists();
+    return newId;
+}
+
+export async function addToPlaylist(id, songs, position = null) {
+    let filePaths = songs.map(song => song.file_path);
+    await invoke('add_to_playlist', { id, filePaths, position });
+    await refreshPlaylists();
+}
+
+export async function removeFromPlaylist(id, entries) {
+    let entryIds = entries.map(entry => entry.entry_id);
+    await invoke('remove_from_playlist', { id, entryIds });
+    await refreshPlaylists();
+}
+
+export async function movePlaylistEntry(id, entry, position) {
+    await invoke('move_playlist_entry', { id, entryId: entry.entry_id, position });
+}
+
+export async function loadPlaylistIntoQueue(id, offset = 0) {
+    let songs = await loadPlaylistSongs(id);
+    if (songs.length == 0) return;
+
+    setQueue(songs, offset);
+    play(songs[offset]);
+}
@@ -0,0 +1,27 @@
+import { invoke } from "@tauri-apps/api/core";
+
+export async function getCoverPatterns() {
+    return JSON.parse(await invoke('get_cover_patterns'));
+}
+
+// An empty list turns off cover files, resetCoverPatterns restores the defaults
+export async function setCoverPatterns(patterns) {
+    return invoke('set_cover_patterns', { patterns });
+}
+
+export async function resetCoverPatterns() {
+    return invoke('reset_cover_patterns');
+}
+
+export async function getArtistSeparators() {
+    return JSON.parse(await invoke('get_artist_separators'));
+}
+
+// A rescan applies the changes to existing songs
+export async function setArtistSeparators({ separators, featuring, exceptions }) {
+    return invoke('set_artist_separators', { separators, featuring, exceptions });
+}
+
+export async function resetArtistSeparators() {
+    return invoke('reset_artist_separators');
+}
@@ -1,5 +1,6 @@
 import { invoke } from "@tauri-apps/api/core";
 import { get, writable } from "svelte/store";
+import { clearThumbnails } from "./covers";
 import { setActiveTab } from "./windowManager";
 
 export const albums = writable([]);
@@ -21,13 +22,18 @@ export async function loadAllAlbums() {
 
 }
 
-export async function loadSongs(album) {
-    return await invoke('get_songs_by_album', { title: album.title, artist: album.artist })
-        .then(songsJSON => {
-            return JSON.parse(songsJSON);
+// Songs come grouped by disc, as { disc_number, disc_subtitle, songs }
+export async function loadDiscs(album) {
+    return await invoke('get_songs_by_album', { title: album.title, artist: album.artist, id: album.id })
+        .then(discsJSON => {
+            return JSON.parse(discsJSON);
         });
 }
 
+export async function loadSongs(album) {
+    return (await loadDiscs(album)).flatMap(disc => disc.songs);
+}
+
 export async function loadAllSongs() {
     return await invoke('get_all_songs')
         .then(songsJSON => {
@@ -36,6 +42,7 @@ export async function loadAllSongs() {
 }
 
 export async function refreshLibrary() {
+    clearThumbnails();
     await invoke('get_all_albums').then(albumsJSON => {
         albums.set(JSON.parse(albumsJSON));
     });
@@ -48,9 +55,30 @@ export async function refreshLibrary() {
 export const activeArtist = writable(null);
 export const openAlbum = writable(null);
 export const songList = writable([]);
+export const discList = writable([]);
 
 export async function refreshSongList(album) {
-    songList.set(await loadSongs(album));
+    let discs = await loadDiscs(album);
+    discList.set(discs);
+    songList.set(discs.flatMap(disc => disc.songs));
+}
+
+// Artist images are stored by the backend, a refresh picks up the new image_path
+async function refreshArtist(name) {
+    await refreshLibrary();
+    if (get(activeArtist)?.name == name) {
+        activeArtist.set(get(artists).find(artist => artist.name == name) ?? null);
+    }
+}
+
+export async function setArtistImage(name, imagePath) {
+    await invoke('set_artist_image', { name, imagePath });
+    await refreshArtist(name);
+}
+
+export async function clearArtistImage(name) {
+    await invoke('clear_artist_image', { name });
+    await refreshArtist(name);
 }
 
 export async function clearActiveArtist() {
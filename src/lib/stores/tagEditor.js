@@ -1,6 +1,8 @@
 import { get, writable } from 'svelte/store';
 import { open } from "@tauri-apps/plugin-dialog";
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { clearThumbnails } from './covers';
 import { addToast } from './notifications';
 import { loadSongs, refreshLibrary, refreshSongList } from './songLibrary';
 
@@ -40,6 +42,66 @@ export async function getNewCover() {
     }
 }
 
+/**
+ * Every field in the song's tag, keyed the way its format stores them (e.g. TPE1, ARTIST, ©ART or TXXX:MOOD)
+ * @returns {Promise<{format: string, fields: {key: string, values: string[], editable: boolean}[]}>}
+ */
+export async function getTagFields(filePath) {
+    return JSON.parse(await invoke('get_tag_fields', { filePath }));
+}
+
+/**
+ * Writes the given fields, leaving the rest of the tag alone. A field with no values is deleted
+ * @param {{key: string, values: string[]}[]} fields
+ */
+export async function setTagFields(filePath, fields) {
+    return await invoke('set_tag_fields', { filePath, fields });
+}
+
+/**
+ * Recent tag edits, newest first
+ * @returns {Promise<{id: number, edited_at: number, undo_of: number?, undone_at: number?, song_count: number, pictures_changed: number, title: string?, album_title: string?}[]>}
+ */
+export async function getEditHistory(limit = 50) {
+    return JSON.parse(await invoke('get_edit_history', { limit }));
+}
+
+/**
+ * Restores the fields an edit changed. Songs changed again since are skipped, embedded pictures can't be restored.
+ * The undo is an edit itself, undoing it redoes the original
+ * @returns {Promise<{file_path: string, error: string?, warning: string?}[]>}
+ */
+export async function undoEdit(id) {
+    return JSON.parse(await invoke('undo_edit', { id }));
+}
+
+export async function undoLastEdit() {
+    let edit = (await getEditHistory()).find(edit => !edit.undone_at);
+    if (!edit) {
+        addToast({ message: 'Nothing to undo', type: 'info', timeout: 3000, dismissable: true });
+        return;
+    }
+
+    try {
+        let results = await undoEdit(edit.id);
+        let failed = results.filter(result => result.error);
+        failed.forEach(result => console.error(`Failed to undo ${result.file_path}: ${result.error}`));
+        results.filter(result => result.warning).forEach(result => console.warn(`${result.file_path}: ${result.warning}`));
+        let restored = results.length - failed.length;
+        addToast({
+            message: `Undid edit of ${restored} song${restored != 1 ? 's' : ''}`
+                + (failed.length > 0 ? `, ${failed.length} failed` : '')
+                + (edit.pictures_changed ? ', embedded covers were not restored' : ''),
+            type: failed.length > 0 ? 'error' : 'success',
+            timeout: 3000,
+            dismissable: true
+        });
+    } catch (error) {
+        addToast({ message: `Failed to undo edit: ${error}`, type: 'error', timeout: 5000, dismissable: true });
+    }
+    refreshLibrary();
+}
+
 export const editInProgress = writable(false);
 export const editProgress = writable(0);
 export const editTotal = writable(0);
@@ -49,47 +111,64 @@ export async function commitChanges() {
         return;
     }
 
+    let songs = get(selectedSongs);
     editInProgress.set(true);
     editProgress.set(0);
-    editTotal.set(get(selectedSongs).length);
+    editTotal.set(songs.length);
     let formData = new FormData(get(editDialog).querySelector('form'));
-    let results = [];
-    let refreshWholeLibrary = false;
+    // Fields left as shown keep each song's own value, emptied fields are cleared
+    let changed = (name, key, toValue) => {
+        let shown = songs.every(song => song[key] == songs[0][key]) ? String(songs[0][key] ?? '') : '';
+        let value = formData.get(name) ?? '';
+        return value === shown ? null : toValue(value);
+    };
+    let patch = {
+        title: changed('title', 'title', String),
+        artist: changed('artist', 'artist', String),
+        albumTitle: changed('album-title', 'album_title', String),
+        albumArtist: changed('album-artist', 'album_artist', String),
+        trackNumber: changed('track-number', 'track_number', Number),
+        discNumber: changed('disc-number', 'disc_number', Number),
+        year: changed('year', 'year', Number),
+        genre: changed('genre', 'genre', String)
+    };
+    let refreshWholeLibrary = patch.albumTitle !== null || patch.albumArtist !== null;
 
-    for (let song of get(selectedSongs)) {
-        if (song.album_title !== formData.get('album-title') || song.album_artist !== formData.get('album-artist')) {
-            refreshWholeLibrary = true;
-        }
-        await invoke('update_metadata_song', {
+    const unlisten = await listen('edit_progress', (event) => {
+        editProgress.set(Number(event.payload.message));
+    });
+    let results = [];
+    try {
+        results = JSON.parse(await invoke('batch_edit_songs', {
+            filePaths: songs.map(song => song.file_path),
+            patch,
             locationOnDisk: get(selectedAlbum).location_on_disk,
-            filePath: song.file_path,
-            coverPath: formData.get('cover-path') || song.cover_path,
-            title: formData.get('title') || song.title,
-            artist: formData.get('artist') || song.artist,
-            albumTitle: formData.get('album-title') || song.album_title,
-            albumArtist: formData.get('album-artist') || song.album_artist,
-            trackNumber: Number(formData.get('track-number')) || song.track_number,
-            discNumber: Number(formData.get('disc-number')) || song.disc_number,
-            year: Number(formData.get('year')) || song.year,
-            genre: formData.get('genre') || song.genre
-        })
-        .then(result => {
-            results.push(result);
-        })
-        .catch(error => {
-            console.error(error);
-        });
-        editProgress.update(n => n + 1);
+            coverPath: formData.get('cover-path') || null,
+            embedCover: formData.get('embed-cover') == 'on',
+            copyCover: formData.get('copy-cover') == 'on'
+        }));
+    } catch (error) {
+        console.error(error);
+        addToast({ message: `Failed to update songs: ${error}`, type: 'error', timeout: 5000, dismissable: true });
     }
+    unlisten();
+
+    let failed = results.filter(result => result.error);
+    failed.forEach(result => console.error(`Failed to update ${result.file_path}: ${result.error}`));
+    let updated = results.length - failed.length;
 
     await new Promise((resolve) => setTimeout(resolve, 500));
     editInProgress.set(false);
     closeEditDialog();
-    addToast({
-        message: `Updated ${results.length} song${results.length > 1 ? 's' : ''}`,
-        type: 'success',
-        timeout: 3000,
-        dismissable: true
-    });
+    if (results.length > 0) {
+        addToast({
+            message: `Updated ${updated} song${updated != 1 ? 's' : ''}` + (failed.length > 0 ? `, ${failed.length} failed` : ''),
+            type: failed.length > 0 ? 'error' : 'success',
+            timeout: 3000,
+            dismissable: true
+        });
+    }
+    // A copied or embedded cover can replace the image that cached thumbnails were made from
+    clearThumbnails();
     refreshWholeLibrary ? refreshLibrary() : refreshSongList(get(selectedAlbum));
-}
\ No newline at end of file
+}
@@ -8,6 +8,10 @@ export function sec2time(seconds) {
     return `${minutes}:${secs < 10 ? '0' + secs : secs}`;
 };
 
+export function ms2time(ms) {
+    return sec2time(ms / 1000);
+};
+
 export async function invokeWithToast(func, args) {
     await invoke(func, args)
         .then(result => {